@@ -0,0 +1,447 @@
+use bevy::{input::InputSystem, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Default location of the bindings file, `BINDINGS_FILE` overrides it.
+const BINDINGS_FILE: &str = "bindings.ron";
+/// Axis deflection that counts as pressed, and that binds an axis when rebinding.
+const AXIS_THRESHOLD: f32 = 0.5;
+/// Key the car camera switches views on, it reads the keyboard rather than actions.
+const CAMERA_KEY: KeyCode = KeyCode::KeyC;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    Throttle,
+    Brake,
+    SteerLeft,
+    SteerRight,
+    Handbrake,
+    Fire,
+    Respawn,
+    // Not read yet, bindable ahead of the scoreboard and chat themselves
+    Scoreboard,
+    Chat,
+    Camera,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::Throttle,
+        Action::Brake,
+        Action::SteerLeft,
+        Action::SteerRight,
+        Action::Handbrake,
+        Action::Fire,
+        Action::Respawn,
+        Action::Scoreboard,
+        Action::Chat,
+        Action::Camera,
+    ];
+}
+
+/// A physical input, gamepad ones are read from the first connected gamepad.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    GamepadButton(GamepadButtonType),
+    // One direction of an axis, so a stick can steer both ways
+    GamepadAxis {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+
+impl Binding {
+    fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::GamepadButton(button) => format!("Pad {:?}", button),
+            Binding::GamepadAxis { axis, positive } => {
+                format!("Pad {:?}{}", axis, if *positive { "+" } else { "-" })
+            }
+        }
+    }
+}
+
+pub type Profile = BTreeMap<Action, Vec<Binding>>;
+
+/// Named binding profiles and the active one, persisted as RON.
+#[derive(Debug, Clone, Serialize, Deserialize, Resource)]
+pub struct BindingsConfig {
+    pub active: String,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+fn default_profile(throttle: KeyCode, brake: KeyCode, left: KeyCode, right: KeyCode) -> Profile {
+    let stick = |positive| Binding::GamepadAxis {
+        axis: GamepadAxisType::LeftStickX,
+        positive,
+    };
+    BTreeMap::from([
+        (
+            Action::Throttle,
+            vec![
+                Binding::Key(throttle),
+                Binding::GamepadButton(GamepadButtonType::RightTrigger2),
+            ],
+        ),
+        (
+            Action::Brake,
+            vec![
+                Binding::Key(brake),
+                Binding::GamepadButton(GamepadButtonType::LeftTrigger2),
+            ],
+        ),
+        (Action::SteerLeft, vec![Binding::Key(left), stick(false)]),
+        (Action::SteerRight, vec![Binding::Key(right), stick(true)]),
+        (
+            Action::Handbrake,
+            vec![
+                Binding::Key(KeyCode::Space),
+                Binding::GamepadButton(GamepadButtonType::South),
+            ],
+        ),
+        (
+            Action::Fire,
+            vec![
+                Binding::Key(KeyCode::KeyF),
+                Binding::GamepadButton(GamepadButtonType::West),
+            ],
+        ),
+        (
+            Action::Respawn,
+            vec![
+                Binding::Key(KeyCode::KeyR),
+                Binding::GamepadButton(GamepadButtonType::North),
+            ],
+        ),
+        (
+            Action::Scoreboard,
+            vec![
+                Binding::Key(KeyCode::Tab),
+                Binding::GamepadButton(GamepadButtonType::Select),
+            ],
+        ),
+        (Action::Chat, vec![Binding::Key(KeyCode::KeyT)]),
+        (
+            Action::Camera,
+            vec![
+                Binding::Key(CAMERA_KEY),
+                Binding::GamepadButton(GamepadButtonType::East),
+            ],
+        ),
+    ])
+}
+
+impl Default for BindingsConfig {
+    fn default() -> Self {
+        Self {
+            active: "arrows".to_string(),
+            profiles: BTreeMap::from([
+                (
+                    "arrows".to_string(),
+                    default_profile(
+                        KeyCode::ArrowUp,
+                        KeyCode::ArrowDown,
+                        KeyCode::ArrowLeft,
+                        KeyCode::ArrowRight,
+                    ),
+                ),
+                (
+                    "wasd".to_string(),
+                    default_profile(KeyCode::KeyW, KeyCode::KeyS, KeyCode::KeyA, KeyCode::KeyD),
+                ),
+            ]),
+        }
+    }
+}
+
+fn bindings_file() -> String {
+    std::env::var("BINDINGS_FILE").unwrap_or_else(|_| BINDINGS_FILE.to_string())
+}
+
+impl BindingsConfig {
+    /// Reads the bindings file, falling back to the defaults if it's missing or broken.
+    pub fn load() -> Self {
+        let path = bindings_file();
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        match ron::from_str::<Self>(&contents) {
+            Ok(config) if config.profiles.contains_key(&config.active) => config,
+            Ok(_) => {
+                println!(
+                    "Bindings in {} have no active profile, using defaults",
+                    path
+                );
+                Self::default()
+            }
+            Err(e) => {
+                println!("Failed to parse bindings {}: {}, using defaults", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let path = bindings_file();
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).unwrap();
+        if let Err(e) = std::fs::write(&path, contents) {
+            println!("Failed to save bindings to {}: {}", path, e);
+        }
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profiles[&self.active]
+    }
+
+    fn profile_mut(&mut self) -> &mut Profile {
+        self.profiles.get_mut(&self.active).unwrap()
+    }
+}
+
+/// Action values for this frame, in `0..=1`, read by gameplay systems instead of raw input.
+#[derive(Debug, Default, Resource)]
+pub struct ActionState {
+    values: HashMap<Action, f32>,
+    just_pressed: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.)
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.value(action) >= AXIS_THRESHOLD
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+/// Action being rebound in the bindings window, waiting for the next input.
+#[derive(Debug, Default, Resource)]
+struct Rebinding {
+    open: bool,
+    capture: Option<Action>,
+}
+
+pub struct BindingsPlugin;
+
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BindingsConfig::load());
+        app.init_resource::<ActionState>();
+        app.init_resource::<Rebinding>();
+        app.add_systems(
+            PreUpdate,
+            (action_state_system, camera_action_system)
+                .chain()
+                .after(InputSystem),
+        );
+        app.add_systems(Update, (capture_binding_system, bindings_ui_system).chain());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn action_state_system(
+    config: Res<BindingsConfig>,
+    rebinding: Res<Rebinding>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    mut state: ResMut<ActionState>,
+) {
+    let gamepad = gamepads.iter().next();
+    let read = |binding: &Binding| -> f32 {
+        match (*binding, gamepad) {
+            (Binding::Key(key), _) => {
+                if keyboard_input.pressed(key) {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            (Binding::GamepadButton(button_type), Some(gamepad)) => {
+                let button = GamepadButton::new(gamepad, button_type);
+                match button_axes.get(button) {
+                    Some(value) => value,
+                    None if buttons.pressed(button) => 1.,
+                    None => 0.,
+                }
+            }
+            (Binding::GamepadAxis { axis, positive }, Some(gamepad)) => {
+                let value = axes.get(GamepadAxis::new(gamepad, axis)).unwrap_or(0.);
+                if positive {
+                    value.max(0.)
+                } else {
+                    (-value).max(0.)
+                }
+            }
+            (_, None) => 0.,
+        }
+    };
+
+    let mut values = HashMap::new();
+    // Nothing drives the car while the player is choosing a binding
+    if rebinding.capture.is_none() {
+        for (action, bindings) in config.profile() {
+            let value = bindings.iter().map(read).fold(0., f32::max);
+            values.insert(*action, value.min(1.));
+        }
+    }
+    let just_pressed = values
+        .iter()
+        .filter(|(action, value)| **value >= AXIS_THRESHOLD && !state.pressed(**action))
+        .map(|(action, _)| *action)
+        .collect();
+    state.values = values;
+    state.just_pressed = just_pressed;
+}
+
+/// Presses the car camera's key for the camera action, so other bindings switch views too.
+fn camera_action_system(
+    actions: Res<ActionState>,
+    mut keyboard_input: ResMut<ButtonInput<KeyCode>>,
+    mut forwarded: Local<bool>,
+) {
+    if *forwarded && !actions.pressed(Action::Camera) {
+        keyboard_input.release(CAMERA_KEY);
+        *forwarded = false;
+    }
+    // Bound to the key itself, the camera sees it already
+    if actions.just_pressed(Action::Camera) && !keyboard_input.pressed(CAMERA_KEY) {
+        keyboard_input.press(CAMERA_KEY);
+        *forwarded = true;
+    }
+}
+
+/// Binds the first key, gamepad button or axis touched after clicking "+" in the bindings window.
+fn capture_binding_system(
+    mut config: ResMut<BindingsConfig>,
+    mut rebinding: ResMut<Rebinding>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+) {
+    let Some(action) = rebinding.capture else {
+        return;
+    };
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        rebinding.capture = None;
+        return;
+    }
+    let mut binding = keyboard_input
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key));
+    if binding.is_none() {
+        binding = buttons
+            .get_just_pressed()
+            .next()
+            .map(|button| Binding::GamepadButton(button.button_type));
+    }
+    if binding.is_none() {
+        if let Some(gamepad) = gamepads.iter().next() {
+            binding = [
+                GamepadAxisType::LeftStickX,
+                GamepadAxisType::LeftStickY,
+                GamepadAxisType::LeftZ,
+                GamepadAxisType::RightStickX,
+                GamepadAxisType::RightStickY,
+                GamepadAxisType::RightZ,
+            ]
+            .into_iter()
+            .find_map(|axis| {
+                let value = axes.get(GamepadAxis::new(gamepad, axis))?;
+                (value.abs() >= AXIS_THRESHOLD).then_some(Binding::GamepadAxis {
+                    axis,
+                    positive: value > 0.,
+                })
+            });
+        }
+    }
+    let Some(binding) = binding else {
+        return;
+    };
+    let bindings = config.profile_mut().entry(action).or_default();
+    if !bindings.contains(&binding) {
+        bindings.push(binding);
+    }
+    config.save();
+    rebinding.capture = None;
+}
+
+/// F2 toggles a window to switch profiles and rebind actions, changes are saved right away.
+fn bindings_ui_system(
+    mut egui_contexts: EguiContexts,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut config: ResMut<BindingsConfig>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        rebinding.open = !rebinding.open;
+        rebinding.capture = None;
+    }
+    if !rebinding.open {
+        return;
+    }
+    let mut changed = false;
+    egui::Window::new("Bindings").show(egui_contexts.ctx_mut(), |ui| {
+        let profiles: Vec<String> = config.profiles.keys().cloned().collect();
+        egui::ComboBox::from_label("Profile")
+            .selected_text(config.active.clone())
+            .show_ui(ui, |ui| {
+                for profile in profiles {
+                    let selected = config.active == profile;
+                    if ui.selectable_label(selected, &profile).clicked() && !selected {
+                        config.active = profile;
+                        changed = true;
+                    }
+                }
+            });
+        egui::Grid::new("bindings").show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(format!("{:?}", action));
+                let bindings = config.profile_mut().entry(action).or_default();
+                let mut remove = None;
+                for (i, binding) in bindings.iter().enumerate() {
+                    if ui
+                        .button(binding.label())
+                        .on_hover_text("Click to remove")
+                        .clicked()
+                    {
+                        remove = Some(i);
+                    }
+                }
+                if let Some(i) = remove {
+                    bindings.remove(i);
+                    changed = true;
+                }
+                if rebinding.capture == Some(action) {
+                    ui.label("Press a key or button, Esc to cancel");
+                } else if ui.button("+").clicked() {
+                    rebinding.capture = Some(action);
+                }
+                ui.end_row();
+            }
+        });
+        if ui.button("Reset profile").clicked() {
+            let defaults = BindingsConfig::default();
+            let active = config.active.clone();
+            if let Some(profile) = defaults.profiles.get(&active) {
+                *config.profile_mut() = profile.clone();
+                changed = true;
+            }
+        }
+    });
+    if changed {
+        config.save();
+    }
+}
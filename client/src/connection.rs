@@ -0,0 +1,323 @@
+//! Connection state machine: main menu, connecting, in game and disconnected,
+//! reconnecting with backoff after the connection drops and disconnecting cleanly on exit.
+use bevy::{app::AppExit, hierarchy::despawn_with_children_recursive, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use bevy_garage_arena_lib::{
+    memory::MemoryClientTransport, profile::MAX_NAME_CHARS, snapshot::SnapshotHistory, CarModel,
+    PlayerProfile,
+};
+use bevy_garage_car::CarWheels;
+use bevy_renet::renet::{transport::NetcodeClientTransport, RenetClient};
+use std::time::Duration;
+
+use crate::{
+    new_renet_client,
+    prediction::{AuthoritativeState, PredictionHistory},
+    projectile::Projectiles,
+    ClientLobby, LocalClientId, NetworkClock, NetworkErrors, NetworkMapping, ServerCompatibility,
+};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ClientState {
+    #[default]
+    MainMenu,
+    Connecting,
+    InGame,
+    Disconnected,
+}
+
+/// Edited in the main menu, the address is also where reconnecting goes.
+#[derive(Debug, Resource)]
+pub struct ConnectForm {
+    pub server_addr: String,
+    /// Sent to the server in the connect token's user data.
+    pub profile: PlayerProfile,
+    /// Why the last attempt failed before anything was sent, e.g. a bad address.
+    error: Option<String>,
+}
+
+impl ConnectForm {
+    pub fn new(server_addr: String) -> Self {
+        Self {
+            server_addr,
+            profile: PlayerProfile::default(),
+            error: None,
+        }
+    }
+}
+
+/// Why the last session ended and when to try again.
+#[derive(Debug, Default, Resource)]
+struct Reconnect {
+    reason: String,
+    /// Failed attempts since the last time in game.
+    attempts: u32,
+    /// `None` when reconnecting can't help, e.g. an incompatible server.
+    timer: Option<Timer>,
+}
+
+impl Reconnect {
+    /// Doubles with every failed attempt, up to `MAX_BACKOFF`.
+    fn backoff(&self) -> Duration {
+        MIN_BACKOFF
+            .saturating_mul(1 << self.attempts.min(16))
+            .min(MAX_BACKOFF)
+    }
+}
+
+pub struct ConnectionPlugin;
+
+impl Plugin for ConnectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<ClientState>();
+        app.init_resource::<Reconnect>();
+        app.add_systems(
+            Update,
+            (
+                main_menu_ui.run_if(in_state(ClientState::MainMenu)),
+                connecting_system.run_if(in_state(ClientState::Connecting)),
+                in_game_system.run_if(in_state(ClientState::InGame)),
+                disconnected_system.run_if(in_state(ClientState::Disconnected)),
+            ),
+        );
+        app.add_systems(
+            OnEnter(ClientState::InGame),
+            |mut reconnect: ResMut<Reconnect>| {
+                reconnect.attempts = 0;
+            },
+        );
+        app.add_systems(OnEnter(ClientState::Disconnected), on_disconnected);
+        app.add_systems(Last, disconnect_on_exit_system);
+    }
+}
+
+/// Connects to `ConnectForm::server_addr`, staying in the current state with the error on failure.
+pub fn connect_system(world: &mut World) {
+    let form = world.resource::<ConnectForm>();
+    let addr = form.server_addr.clone();
+    match new_renet_client(&addr, &form.profile) {
+        Ok((client, transport)) => {
+            println!("Connecting to {}", addr);
+            world.insert_resource(LocalClientId(transport.client_id()));
+            world.insert_resource(client);
+            world.insert_resource(transport);
+            world.resource_mut::<ConnectForm>().error = None;
+            world
+                .resource_mut::<NextState<ClientState>>()
+                .set(ClientState::Connecting);
+        }
+        Err(e) => {
+            println!("Can't connect to {}: {}", addr, e);
+            world.resource_mut::<ConnectForm>().error = Some(e);
+        }
+    }
+}
+
+/// Drops the connection and everything it created, then shows the menu.
+fn back_to_menu(world: &mut World) {
+    if let Some(mut client) = world.get_resource_mut::<RenetClient>() {
+        client.disconnect();
+    }
+    if let Some(mut transport) = world.get_resource_mut::<NetcodeClientTransport>() {
+        transport.disconnect();
+    }
+    world.remove_resource::<RenetClient>();
+    world.remove_resource::<NetcodeClientTransport>();
+    world.remove_resource::<MemoryClientTransport>();
+    world.remove_resource::<LocalClientId>();
+    clear_session(world);
+    *world.resource_mut::<Reconnect>() = Reconnect::default();
+    world
+        .resource_mut::<NextState<ClientState>>()
+        .set(ClientState::MainMenu);
+}
+
+fn main_menu_ui(mut egui_contexts: EguiContexts, mut form: ResMut<ConnectForm>, mut cmd: Commands) {
+    egui::Window::new("Bevy Garage Arena")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+        .show(egui_contexts.ctx_mut(), |ui| {
+            egui::Grid::new("connect_form").show(ui, |ui| {
+                ui.label("Server");
+                ui.text_edit_singleline(&mut form.server_addr);
+                ui.end_row();
+                ui.label("Name");
+                ui.add(
+                    egui::TextEdit::singleline(&mut form.profile.name).char_limit(MAX_NAME_CHARS),
+                );
+                ui.end_row();
+                ui.label("Color");
+                ui.color_edit_button_srgb(&mut form.profile.color);
+                ui.end_row();
+                ui.label("Car");
+                egui::ComboBox::from_id_source("car_model")
+                    .selected_text(format!("{:?}", form.profile.car))
+                    .show_ui(ui, |ui| {
+                        for car in CarModel::ALL {
+                            ui.selectable_value(&mut form.profile.car, car, format!("{:?}", car));
+                        }
+                    });
+                ui.end_row();
+            });
+            if let Some(error) = &form.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+            if ui.button("Connect").clicked() {
+                cmd.add(connect_system);
+            }
+        });
+}
+
+fn connecting_system(
+    mut egui_contexts: EguiContexts,
+    form: Res<ConnectForm>,
+    client: Res<RenetClient>,
+    mut next_state: ResMut<NextState<ClientState>>,
+    mut cmd: Commands,
+) {
+    if client.is_connected() {
+        next_state.set(ClientState::InGame);
+        return;
+    }
+    if client.is_disconnected() {
+        next_state.set(ClientState::Disconnected);
+        return;
+    }
+    egui::Window::new("Connecting")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+        .show(egui_contexts.ctx_mut(), |ui| {
+            ui.label(format!("Connecting to {}...", form.server_addr));
+            if ui.button("Cancel").clicked() {
+                cmd.add(back_to_menu);
+            }
+        });
+}
+
+fn in_game_system(client: Res<RenetClient>, mut next_state: ResMut<NextState<ClientState>>) {
+    if client.is_disconnected() {
+        next_state.set(ClientState::Disconnected);
+    }
+}
+
+fn disconnect_reason(world: &World) -> String {
+    if let ServerCompatibility::Incompatible(reason) = world.resource::<ServerCompatibility>() {
+        return format!("incompatible server: {}", reason);
+    }
+    let reason = world
+        .get_resource::<RenetClient>()
+        .and_then(|client| client.disconnect_reason())
+        .map_or("connection lost".to_string(), |reason| reason.to_string());
+    match world.resource::<NetworkErrors>().0.last() {
+        Some(error) => format!("{}: {}", reason, error),
+        None => reason,
+    }
+}
+
+fn on_disconnected(world: &mut World) {
+    let reason = disconnect_reason(world);
+    println!("Disconnected: {}", reason);
+    // Reconnecting to the in-process host or an incompatible server can't help
+    let incompatible = matches!(
+        *world.resource::<ServerCompatibility>(),
+        ServerCompatibility::Incompatible(_)
+    );
+    let retry = !incompatible && world.contains_resource::<NetcodeClientTransport>();
+    clear_session(world);
+    let mut reconnect = world.resource_mut::<Reconnect>();
+    let backoff = reconnect.backoff();
+    reconnect.reason = reason;
+    reconnect.timer = retry.then(|| Timer::new(backoff, TimerMode::Once));
+}
+
+fn retry_connection(world: &mut World) {
+    world.resource_mut::<Reconnect>().attempts += 1;
+    connect_system(world);
+    // A failure here never reached the server, wait and try again
+    if let Some(error) = world.resource_mut::<ConnectForm>().error.take() {
+        let mut reconnect = world.resource_mut::<Reconnect>();
+        let backoff = reconnect.backoff();
+        reconnect.reason = error;
+        reconnect.timer = Some(Timer::new(backoff, TimerMode::Once));
+    }
+}
+
+fn disconnected_system(
+    mut egui_contexts: EguiContexts,
+    time: Res<Time>,
+    mut reconnect: ResMut<Reconnect>,
+    mut cmd: Commands,
+) {
+    let mut retry_now = false;
+    egui::Window::new("Disconnected")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+        .show(egui_contexts.ctx_mut(), |ui| {
+            ui.label(&reconnect.reason);
+            if let Some(timer) = &reconnect.timer {
+                ui.label(format!(
+                    "Reconnecting in {:.0}s (attempt {})",
+                    timer.remaining_secs().ceil(),
+                    reconnect.attempts + 1
+                ));
+                retry_now = ui.button("Reconnect now").clicked();
+            }
+            if ui.button("Main menu").clicked() {
+                cmd.add(back_to_menu);
+            }
+        });
+    let Some(timer) = reconnect.timer.as_mut() else {
+        return;
+    };
+    if retry_now || timer.tick(time.delta()).finished() {
+        reconnect.timer = None;
+        cmd.add(retry_connection);
+    }
+}
+
+/// Tells the server right away so it frees the player's slot, instead of timing out.
+fn disconnect_on_exit_system(
+    mut exit: EventReader<AppExit>,
+    client: Option<ResMut<RenetClient>>,
+    transport: Option<ResMut<NetcodeClientTransport>>,
+) {
+    if exit.read().next().is_none() {
+        return;
+    }
+    if let Some(mut client) = client {
+        client.disconnect();
+    }
+    if let Some(mut transport) = transport {
+        transport.disconnect();
+    }
+}
+
+/// Despawns everything the last session created and resets what the next handshake sets up.
+fn clear_session(world: &mut World) {
+    let players = std::mem::take(&mut world.resource_mut::<ClientLobby>().players);
+    for info in players.into_values() {
+        let wheels = world
+            .get::<CarWheels>(info.client_entity)
+            .map(|wheels| wheels.entities);
+        for entity in wheels.into_iter().flatten() {
+            despawn_with_children_recursive(world, entity);
+        }
+        despawn_with_children_recursive(world, info.client_entity);
+    }
+    for entity in world.resource_mut::<Projectiles>().clear() {
+        despawn_with_children_recursive(world, entity);
+    }
+    world.resource_mut::<NetworkMapping>().0.clear();
+    world.resource_mut::<NetworkErrors>().0.clear();
+    *world.resource_mut::<NetworkClock>() = NetworkClock::default();
+    *world.resource_mut::<SnapshotHistory>() = SnapshotHistory::default();
+    *world.resource_mut::<ServerCompatibility>() = ServerCompatibility::Pending;
+    world.resource_mut::<AuthoritativeState>().0 = None;
+    world.resource_mut::<PredictionHistory>().clear();
+}
@@ -0,0 +1,50 @@
+//! Car health as the server reports it, shown for the controlled car.
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{connection::ClientState, ControlledPlayer};
+
+/// Health of a full car, from `ServerInfo`.
+#[derive(Debug, Resource)]
+pub struct MaxHealth(pub f32);
+
+impl Default for MaxHealth {
+    fn default() -> Self {
+        Self(100.)
+    }
+}
+
+#[derive(Debug, Component)]
+pub struct Health(pub f32);
+
+fn health_ui(
+    mut egui_contexts: EguiContexts,
+    max_health: Res<MaxHealth>,
+    cars: Query<&Health, With<ControlledPlayer>>,
+) {
+    let Ok(health) = cars.get_single() else {
+        return;
+    };
+    egui::Area::new("health")
+        .anchor(egui::Align2::LEFT_BOTTOM, [10., -10.])
+        .show(egui_contexts.ctx_mut(), |ui| {
+            if health.0 > 0. {
+                ui.add(
+                    egui::ProgressBar::new(health.0 / max_health.0)
+                        .desired_width(200.)
+                        .text(format!("{:.0} / {:.0}", health.0, max_health.0)),
+                );
+            } else {
+                ui.colored_label(egui::Color32::RED, "Destroyed, respawning...");
+            }
+        });
+}
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MaxHealth>();
+        app.add_systems(Update, health_ui.run_if(in_state(ClientState::InGame)));
+    }
+}
@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+use bevy_garage_arena_lib::{quantize::WheelAnchors, EntityState};
+use bevy_garage_car::{CarWheels, Wheel};
+use std::{collections::VecDeque, time::Duration};
+
+use crate::NetworkClock;
+
+const MAX_SNAPSHOTS: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CarSnapshot {
+    pub tick: u32,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub wheels: [(Vec3, Quat); 4],
+}
+
+impl CarSnapshot {
+    pub fn new(tick: u32, state: &EntityState, anchors: &WheelAnchors) -> Self {
+        let car = state.car_transform();
+        let wheels = state.wheel_transforms(anchors);
+        Self {
+            tick,
+            translation: car.translation,
+            rotation: car.rotation,
+            wheels: wheels.map(|wheel| (wheel.translation, wheel.rotation)),
+        }
+    }
+}
+
+/// Recent server states of a remote car, ordered by tick.
+#[derive(Debug, Default, Component)]
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<CarSnapshot>,
+    /// Snapshots before this tick are from before a respawn.
+    min_tick: u32,
+}
+
+impl SnapshotBuffer {
+    pub fn push(&mut self, snapshot: CarSnapshot) {
+        if snapshot.tick < self.min_tick
+            || self
+                .snapshots
+                .back()
+                .is_some_and(|last| snapshot.tick <= last.tick)
+        {
+            return;
+        }
+        if self.snapshots.len() == MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// Forgets the car's motion before it was moved at `tick`.
+    pub fn reset(&mut self, tick: u32) {
+        self.min_tick = tick;
+        self.snapshots.retain(|snapshot| snapshot.tick >= tick);
+    }
+
+    /// State at fractional `tick`, extrapolated at most `max_extrapolation` ticks past the newest.
+    fn sample(&mut self, tick: f64, max_extrapolation: f64) -> Option<CarSnapshot> {
+        // Keep one snapshot at or before `tick` to interpolate from
+        while self.snapshots.len() > 2 && (self.snapshots[1].tick as f64) <= tick {
+            self.snapshots.pop_front();
+        }
+        let first = *self.snapshots.front()?;
+        if self.snapshots.len() == 1 || tick <= first.tick as f64 {
+            return Some(first);
+        }
+        let (a, b) = (self.snapshots[0], self.snapshots[1]);
+        let span = (b.tick - a.tick) as f64;
+        let max_s = 1. + max_extrapolation / span;
+        let s = ((tick - a.tick as f64) / span).min(max_s) as f32;
+        Some(blend(&a, &b, s))
+    }
+}
+
+/// Interpolates for `s` in 0..=1, extrapolates along the same motion beyond 1.
+fn blend(a: &CarSnapshot, b: &CarSnapshot, s: f32) -> CarSnapshot {
+    let rotate = |from: Quat, to: Quat| {
+        if s <= 1. {
+            from.slerp(to, s)
+        } else {
+            let delta = (to * from.inverse()).to_scaled_axis();
+            Quat::from_scaled_axis(delta * (s - 1.)) * to
+        }
+    };
+    CarSnapshot {
+        tick: b.tick,
+        translation: a.translation.lerp(b.translation, s),
+        rotation: rotate(a.rotation, b.rotation),
+        wheels: std::array::from_fn(|i| {
+            (
+                a.wheels[i].0.lerp(b.wheels[i].0, s),
+                rotate(a.wheels[i].1, b.wheels[i].1),
+            )
+        }),
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct InterpolationSettings {
+    /// How far in the past remote cars are rendered, should cover a couple of snapshots.
+    pub delay: Duration,
+    /// Cap on extrapolating past the newest snapshot when packets are lost.
+    pub max_extrapolation: Duration,
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(100),
+            max_extrapolation: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Server tick remote cars are currently rendered at, trails `NetworkClock::last_tick` by the delay.
+#[derive(Debug, Default, Resource)]
+struct RenderTick(Option<f64>);
+
+pub struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InterpolationSettings>();
+        app.init_resource::<RenderTick>();
+        app.add_systems(
+            Update,
+            (render_tick_system, interpolate_remote_cars_system).chain(),
+        );
+    }
+}
+
+fn render_tick_system(
+    time: Res<Time>,
+    clock: Res<NetworkClock>,
+    settings: Res<InterpolationSettings>,
+    mut render_tick: ResMut<RenderTick>,
+) {
+    if clock.last_tick == 0 {
+        return;
+    }
+    let tick_rate = clock.tick_rate as f64;
+    let target = clock.last_tick as f64 - settings.delay.as_secs_f64() * tick_rate;
+    let advanced = render_tick
+        .0
+        .map(|tick| tick + time.delta_seconds_f64() * tick_rate);
+    render_tick.0 = Some(match advanced {
+        // Drift slowly towards the target so bunched packets don't make cars jump
+        Some(tick) if (target - tick).abs() < tick_rate / 4. => tick + (target - tick) * 0.05,
+        _ => target,
+    });
+}
+
+fn interpolate_remote_cars_system(
+    settings: Res<InterpolationSettings>,
+    clock: Res<NetworkClock>,
+    render_tick: Res<RenderTick>,
+    mut cars: Query<(&mut SnapshotBuffer, &CarWheels, &mut Transform)>,
+    mut wheels: Query<&mut Transform, (With<Wheel>, Without<SnapshotBuffer>)>,
+) {
+    let Some(tick) = render_tick.0 else {
+        return;
+    };
+    let max_extrapolation = settings.max_extrapolation.as_secs_f64() * clock.tick_rate as f64;
+    for (mut buffer, car_wheels, mut transform) in cars.iter_mut() {
+        let Some(snapshot) = buffer.sample(tick, max_extrapolation) else {
+            continue;
+        };
+        transform.translation = snapshot.translation;
+        transform.rotation = snapshot.rotation;
+        for (i, e) in car_wheels.entities.iter().enumerate() {
+            if let Ok(mut wheel_transform) = wheels.get_mut(*e) {
+                wheel_transform.translation = snapshot.wheels[i].0;
+                wheel_transform.rotation = snapshot.wheels[i].1;
+            }
+        }
+    }
+}
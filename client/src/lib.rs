@@ -0,0 +1,659 @@
+use bevy::{
+    diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
+    prelude::*,
+};
+use bevy_egui::{EguiContexts, EguiPlugin};
+use bevy_garage_arena_lib::{
+    auth::{private_key_from_env, random_client_id, request_token, unsecure_from_env, TokenIssuer},
+    connection_config,
+    level::DEFAULT_LEVEL,
+    memory::{MemoryClientPlugin, MemoryClientTransport},
+    protocol::decode_server_message,
+    quantize::{wheel_anchors_system, WheelAnchors},
+    setup_level,
+    snapshot::SnapshotHistory,
+    ClientChannel, Level, NetworkedEntities, PlayerCommand, PlayerInput, PlayerProfile,
+    ProtocolVersion, ServerChannel, ServerMessages, DEFAULT_TICK_RATE, PROTOCOL_ID,
+};
+use bevy_garage_camera::CarCameraPlugin;
+use bevy_rapier3d::prelude::Velocity;
+use bevy_renet::{
+    client_connected,
+    renet::{
+        transport::{ClientAuthentication, NetcodeClientTransport, NetcodeTransportError},
+        ClientId, RenetClient,
+    },
+    transport::NetcodeClientPlugin,
+    RenetClientPlugin,
+};
+use bindings::{Action, ActionState, BindingsPlugin};
+use connection::{connect_system, ClientState, ConnectForm, ConnectionPlugin};
+use health::{Health, HealthPlugin, MaxHealth};
+use interpolation::{CarSnapshot, InterpolationPlugin, SnapshotBuffer};
+use nameplate::{spawn_nameplate, NameplatePlugin};
+use prediction::{
+    prediction_step_system, reconcile_system, snap_car, AuthoritativeCar, AuthoritativeState,
+    PredictionPlugin,
+};
+use projectile::{ProjectilePlugin, Projectiles};
+use renet_visualizer::{RenetClientVisualizer, RenetVisualizerStyle};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::SystemTime,
+};
+
+mod bindings;
+mod connection;
+mod health;
+mod interpolation;
+mod nameplate;
+mod prediction;
+mod projectile;
+
+#[derive(Component)]
+struct ControlledPlayer;
+
+/// Id the server knows this client by, its `PlayerCreate` is the controlled car.
+#[derive(Debug, Resource)]
+struct LocalClientId(ClientId);
+
+#[derive(Default, Resource)]
+struct NetworkMapping(HashMap<Entity, Entity>);
+
+#[derive(Debug)]
+struct PlayerInfo {
+    client_entity: Entity,
+    server_entity: Entity,
+}
+
+#[derive(Debug, Default, Resource)]
+struct ClientLobby {
+    players: HashMap<ClientId, PlayerInfo>,
+}
+
+/// Errors shown to the player instead of crashing the client, most recent last.
+#[derive(Debug, Default, Resource)]
+struct NetworkErrors(Vec<String>);
+
+impl NetworkErrors {
+    const MAX: usize = 8;
+
+    fn push(&mut self, error: String) {
+        println!("Network error: {}", error);
+        if self.0.len() == Self::MAX {
+            self.0.remove(0);
+        }
+        self.0.push(error);
+    }
+}
+
+/// Snapshot ordering and round trip, both in server ticks.
+#[derive(Debug, Resource)]
+struct NetworkClock {
+    tick_rate: u32,
+    last_tick: u32,
+    rtt_ticks: Option<u32>,
+    // Input sequence and `ack_tick` of inputs not yet seen applied in a snapshot
+    pending_inputs: VecDeque<(u32, u32)>,
+}
+
+impl NetworkClock {
+    const MAX_PENDING_INPUTS: usize = 256;
+}
+
+impl Default for NetworkClock {
+    fn default() -> Self {
+        Self {
+            tick_rate: DEFAULT_TICK_RATE,
+            last_tick: 0,
+            rtt_ticks: None,
+            pending_inputs: VecDeque::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+enum ServerCompatibility {
+    #[default]
+    Pending,
+    /// The handshake announced a level with this hash, its `ServerMessages::Level` comes next.
+    AwaitingLevel(u64),
+    Compatible,
+    Incompatible(String),
+}
+
+/// The level the server sent, if it's the one its handshake announced.
+fn server_level(ron: &str, level_hash: u64) -> Result<Level, String> {
+    let level = Level::from_ron(ron).map_err(|e| format!("invalid level from server: {}", e))?;
+    if level.hash() != level_hash {
+        return Err(format!(
+            "level from server doesn't match its hash {:016x}",
+            level_hash
+        ));
+    }
+    Ok(level)
+}
+
+/// How the client reaches the server.
+pub enum Connection {
+    /// Netcode over UDP, connects right away if an address is given, otherwise starts in the menu.
+    Netcode { server_addr: Option<String> },
+    /// Server app in the same process, see `host` in the launcher.
+    Memory(MemoryClientTransport),
+}
+
+/// `RENET_SERVER_ADDR`, or a local server.
+fn default_server_addr() -> String {
+    std::env::var("RENET_SERVER_ADDR").unwrap_or_else(|_| "127.0.0.1:5000".to_string())
+}
+
+fn new_renet_client(
+    addr: &str,
+    profile: &PlayerProfile,
+) -> Result<(RenetClient, NetcodeClientTransport), String> {
+    let client = RenetClient::new(connection_config());
+
+    // Host names are fine too, e.g. a deploy host's domain
+    let server_addr: SocketAddr = addr
+        .to_socket_addrs()
+        .map_err(|e| format!("invalid server address {}: {}", addr, e))?
+        .next()
+        .ok_or_else(|| format!("server address {} resolves to no address", addr))?;
+    // Any local interface of the server's address family unless pinned to one
+    let local_addr: SocketAddr = match std::env::var("RENET_CLIENT_SOCKET") {
+        Ok(local_addr) => local_addr
+            .parse()
+            .map_err(|e| format!("invalid RENET_CLIENT_SOCKET {}: {}", local_addr, e))?,
+        Err(_) if server_addr.is_ipv4() => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        Err(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local_addr)
+        .map_err(|e| format!("can't bind socket {}: {}", local_addr, e))?;
+    println!("socket bound to {}", socket.local_addr().unwrap());
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    // Only used without a token service, which assigns the id itself
+    let client_id = random_client_id();
+    let user_data = profile.to_user_data();
+    let authentication = if unsecure_from_env() {
+        println!("RENET_UNSECURE set, connecting without connect token");
+        ClientAuthentication::Unsecure {
+            client_id,
+            protocol_id: PROTOCOL_ID,
+            server_addr,
+            user_data: Some(user_data),
+        }
+    } else if let Ok(token_addr) = std::env::var("RENET_TOKEN_ADDR") {
+        println!("requesting connect token from {}", &token_addr);
+        let token_addr = token_addr
+            .parse()
+            .map_err(|e| format!("invalid RENET_TOKEN_ADDR {}: {}", token_addr, e))?;
+        let connect_token = request_token(token_addr, &user_data)
+            .map_err(|e| format!("can't get connect token: {}", e))?;
+        ClientAuthentication::Secure { connect_token }
+    } else if let Some(private_key) = private_key_from_env()? {
+        let issuer = TokenIssuer::new(private_key, vec![server_addr]);
+        let connect_token = issuer
+            .issue(client_id, Some(&user_data))
+            .map_err(|e| format!("can't issue connect token: {}", e))?;
+        ClientAuthentication::Secure { connect_token }
+    } else {
+        return Err(
+            "RENET_TOKEN_ADDR or RENET_PRIVATE_KEY required, or set RENET_UNSECURE=1 for dev"
+                .to_string(),
+        );
+    };
+
+    let transport = NetcodeClientTransport::new(current_time, authentication, socket)
+        .map_err(|e| format!("can't create transport: {}", e))?;
+
+    Ok((client, transport))
+}
+
+pub fn run(connection: Connection) {
+    let mut app = App::new();
+    app.insert_resource(bevy_garage_car::CarRes {
+        show_rays: true,
+        ..default()
+    });
+    app.add_plugins((
+        DefaultPlugins,
+        RenetClientPlugin,
+        NetcodeClientPlugin,
+        MemoryClientPlugin,
+        FrameTimeDiagnosticsPlugin,
+        LogDiagnosticsPlugin::default(),
+        EguiPlugin,
+        CarCameraPlugin,
+        PredictionPlugin,
+        InterpolationPlugin,
+        BindingsPlugin,
+        NameplatePlugin,
+        ProjectilePlugin,
+        HealthPlugin,
+        ConnectionPlugin,
+    ));
+    app.add_event::<PlayerCommand>();
+    app.insert_resource(ClientLobby::default());
+    app.insert_resource(ServerCompatibility::default());
+    app.insert_resource(NetworkErrors::default());
+    app.insert_resource(NetworkClock::default());
+    app.insert_resource(SnapshotHistory::default());
+    app.insert_resource(PlayerInput::default());
+    app.insert_resource(Level::load(DEFAULT_LEVEL).unwrap_or_else(|e| {
+        println!("{}, using the built-in level.", e);
+        Level::builtin()
+    }));
+    match connection {
+        Connection::Netcode {
+            server_addr: Some(server_addr),
+        } => {
+            app.insert_resource(ConnectForm::new(server_addr));
+            app.add_systems(Startup, connect_system);
+        }
+        Connection::Netcode { server_addr: None } => {
+            app.insert_resource(ConnectForm::new(default_server_addr()));
+        }
+        Connection::Memory(transport) => {
+            app.insert_resource(ConnectForm::new(default_server_addr()));
+            app.insert_resource(LocalClientId(transport.client_id));
+            app.insert_resource(RenetClient::new(connection_config()));
+            app.insert_resource(transport);
+            app.world
+                .resource_mut::<NextState<ClientState>>()
+                .set(ClientState::Connecting);
+        }
+    }
+
+    app.insert_resource(NetworkMapping::default());
+
+    app.add_systems(
+        Update,
+        (
+            player_input,
+            respawn_command_system.run_if(in_state(ClientState::InGame)),
+            (client_send_player_commands, client_sync_players).run_if(client_connected),
+        ),
+    );
+    app.add_systems(
+        FixedUpdate,
+        (reconcile_system, client_send_input, prediction_step_system)
+            .chain()
+            .run_if(client_connected),
+    );
+
+    app.insert_resource(RenetClientVisualizer::<200>::new(
+        RenetVisualizerStyle::default(),
+    ));
+
+    app.add_systems(Startup, bevy_garage_car::car_start_system);
+    // Again whenever the server's level replaces ours
+    app.add_systems(Update, setup_level.run_if(resource_changed::<Level>));
+    app.add_systems(PostUpdate, wheel_anchors_system);
+    app.add_systems(
+        Update,
+        (
+            update_visulizer_system.run_if(resource_exists::<RenetClient>),
+            transport_error_system,
+            network_status_ui.run_if(in_state(ClientState::InGame)),
+        ),
+    );
+
+    app.run();
+}
+
+fn transport_error_system(
+    mut renet_error: EventReader<NetcodeTransportError>,
+    compatibility: Res<ServerCompatibility>,
+    mut errors: ResMut<NetworkErrors>,
+) {
+    for e in renet_error.read() {
+        // Disconnecting after a failed handshake is expected, the reason is shown already
+        if let ServerCompatibility::Incompatible(_) = *compatibility {
+            continue;
+        }
+        errors.push(e.to_string());
+    }
+}
+
+fn network_status_ui(mut egui_contexts: EguiContexts, errors: Res<NetworkErrors>) {
+    if !errors.0.is_empty() {
+        bevy_egui::egui::Window::new("Network errors").show(egui_contexts.ctx_mut(), |ui| {
+            for error in errors.0.iter() {
+                ui.label(error);
+            }
+        });
+    }
+}
+
+fn update_visulizer_system(
+    mut egui_contexts: EguiContexts,
+    mut visualizer: ResMut<RenetClientVisualizer<200>>,
+    client: Res<RenetClient>,
+    clock: Res<NetworkClock>,
+    mut show_visualizer: Local<bool>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+) {
+    visualizer.add_network_info(client.network_info());
+    if keyboard_input.just_pressed(KeyCode::F1) {
+        *show_visualizer = !*show_visualizer;
+    }
+    if *show_visualizer {
+        visualizer.show_window(egui_contexts.ctx_mut());
+        bevy_egui::egui::Window::new("Ticks").show(egui_contexts.ctx_mut(), |ui| {
+            ui.label(format!("server tick: {}", clock.last_tick));
+            if let Some(rtt_ticks) = clock.rtt_ticks {
+                ui.label(format!("rtt: {} ticks", rtt_ticks));
+            }
+        });
+    }
+}
+
+/// Asks the server to put the car back on a spawn point, it also does so by itself when flipped.
+fn respawn_command_system(
+    actions: Res<ActionState>,
+    mut player_commands: EventWriter<PlayerCommand>,
+) {
+    if actions.just_pressed(Action::Respawn) {
+        player_commands.send(PlayerCommand::Respawn);
+    }
+}
+
+/// Reads the driving actions, see `bindings` for what they are mapped to.
+fn player_input(actions: Res<ActionState>, mut player_input: ResMut<PlayerInput>) {
+    player_input.set_controls(
+        actions.value(Action::Throttle),
+        actions.value(Action::Brake),
+        actions.value(Action::SteerRight) - actions.value(Action::SteerLeft),
+        actions.pressed(Action::Handbrake),
+    );
+}
+
+fn client_send_input(
+    mut player_input: ResMut<PlayerInput>,
+    mut clock: ResMut<NetworkClock>,
+    mut client: ResMut<RenetClient>,
+) {
+    player_input.sequence += 1;
+    player_input.ack_tick = clock.last_tick;
+    if clock.pending_inputs.len() == NetworkClock::MAX_PENDING_INPUTS {
+        clock.pending_inputs.pop_front();
+    }
+    clock
+        .pending_inputs
+        .push_back((player_input.sequence, player_input.ack_tick));
+    let input_message = bincode::serialize(&*player_input).unwrap();
+    client.send_message(ClientChannel::Input, input_message);
+}
+
+fn client_send_player_commands(
+    mut player_commands: EventReader<PlayerCommand>,
+    mut client: ResMut<RenetClient>,
+) {
+    for command in player_commands.read() {
+        let command_message = bincode::serialize(command).unwrap();
+        client.send_message(ClientChannel::Command, command_message);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn client_sync_players(
+    mut cmd: Commands,
+    mut client: ResMut<RenetClient>,
+    local_id: Res<LocalClientId>,
+    mut lobby: ResMut<ClientLobby>,
+    mut network_mapping: ResMut<NetworkMapping>,
+    mut compatibility: ResMut<ServerCompatibility>,
+    mut errors: ResMut<NetworkErrors>,
+    mut clock: ResMut<NetworkClock>,
+    mut authoritative: ResMut<AuthoritativeState>,
+    mut snapshot_history: ResMut<SnapshotHistory>,
+    mut projectiles: ResMut<Projectiles>,
+    mut max_health: ResMut<MaxHealth>,
+    car_res: Res<bevy_garage_car::CarRes>,
+    mut level: ResMut<Level>,
+    mut cars: Query<(&WheelAnchors, Option<&mut SnapshotBuffer>)>,
+) {
+    let client_id = local_id.0;
+    while let Some(message) = client.receive_message(ServerChannel::ServerMessages) {
+        if !matches!(*compatibility, ServerCompatibility::Compatible) {
+            let awaiting_level = match *compatibility {
+                ServerCompatibility::AwaitingLevel(level_hash) => Some(level_hash),
+                _ => None,
+            };
+            let reason = match (awaiting_level, decode_server_message(&message)) {
+                (Some(level_hash), Ok(ServerMessages::Level { ron })) => {
+                    match server_level(&ron, level_hash) {
+                        Ok(server_level) => {
+                            println!("Switched to the server's level {:016x}.", level_hash);
+                            *level = server_level;
+                            *compatibility = ServerCompatibility::Compatible;
+                            continue;
+                        }
+                        Err(reason) => reason,
+                    }
+                }
+                // Sent instead of the handshake, shown once the server drops the connection
+                (_, Ok(ServerMessages::Rejected { reason })) => {
+                    errors.push(format!("rejected by server: {}", reason));
+                    continue;
+                }
+                (Some(_), _) => "server did not send its level".to_string(),
+                (None, Ok(ServerMessages::Handshake(server_version))) => {
+                    match ProtocolVersion::current(&level).check_compatible(&server_version) {
+                        Ok(()) if server_version.level_hash == level.hash() => {
+                            println!("Server {} compatible.", server_version.crate_version);
+                            *compatibility = ServerCompatibility::Compatible;
+                            continue;
+                        }
+                        Ok(()) => {
+                            println!(
+                                "Server {} compatible, its level {:016x} differs from ours {:016x}.",
+                                server_version.crate_version,
+                                server_version.level_hash,
+                                level.hash()
+                            );
+                            *compatibility =
+                                ServerCompatibility::AwaitingLevel(server_version.level_hash);
+                            continue;
+                        }
+                        Err(reason) => reason,
+                    }
+                }
+                (None, _) => "server did not send a protocol handshake".to_string(),
+            };
+            println!("Incompatible server: {}", reason);
+            *compatibility = ServerCompatibility::Incompatible(reason);
+            client.disconnect();
+            return;
+        }
+        let server_message = match decode_server_message(&message) {
+            Ok(server_message) => server_message,
+            Err(e) => {
+                errors.push(format!("server message dropped: {}", e));
+                continue;
+            }
+        };
+        match server_message {
+            // Only needed when the handshake showed another level
+            ServerMessages::Handshake(_) | ServerMessages::Level { .. } => {}
+            ServerMessages::ServerInfo {
+                tick_rate,
+                physics,
+                max_health: server_max_health,
+            } => {
+                println!("Server tick rate {}, physics {:?}.", tick_rate, physics);
+                clock.tick_rate = tick_rate.max(1);
+                max_health.0 = server_max_health;
+                cmd.insert_resource(physics);
+            }
+            ServerMessages::PlayerCreate {
+                id,
+                translation,
+                rotation,
+                entity,
+                profile,
+            } => {
+                println!("Player {} connected as '{}'.", id, profile.name);
+
+                let is_player = client_id == id;
+
+                let transform = Transform::from_translation(translation.into())
+                    .with_rotation(Quat::from_array(rotation));
+                let client_entity = bevy_garage_car::spawn_car(
+                    &mut cmd,
+                    &car_res.car_scene.as_ref().unwrap(),
+                    &car_res.wheel_scene.as_ref().unwrap(),
+                    is_player,
+                    transform,
+                );
+
+                cmd.entity(client_entity).insert(Health(max_health.0));
+                if is_player {
+                    cmd.entity(client_entity).insert(ControlledPlayer);
+                } else {
+                    cmd.entity(client_entity).insert(SnapshotBuffer::default());
+                    spawn_nameplate(&mut cmd, client_entity, &profile);
+                }
+
+                let player_info = PlayerInfo {
+                    server_entity: entity,
+                    client_entity,
+                };
+                lobby.players.insert(id, player_info);
+                network_mapping.0.insert(entity, client_entity);
+            }
+            ServerMessages::PlayerRemove { id } => {
+                println!("Player {} disconnected.", id);
+                if let Some(PlayerInfo {
+                    server_entity,
+                    client_entity,
+                }) = lobby.players.remove(&id)
+                {
+                    cmd.entity(client_entity).despawn();
+                    network_mapping.0.remove(&server_entity);
+                }
+            }
+            ServerMessages::PlayerHealth { id, health } => {
+                if let Some(info) = lobby.players.get(&id) {
+                    cmd.entity(info.client_entity).insert(Health(health));
+                }
+            }
+            ServerMessages::PlayerDestroyed { id, by } => match by {
+                Some(by) => println!("Player {} destroyed by {}.", id, by),
+                None => println!("Player {} destroyed.", id),
+            },
+            ServerMessages::PlayerRespawn {
+                id,
+                tick,
+                translation,
+                rotation,
+            } => {
+                if let Some(info) = lobby.players.get(&id) {
+                    let car = info.client_entity;
+                    let transform = Transform::from_translation(translation.into())
+                        .with_rotation(Quat::from_array(rotation));
+                    cmd.add(move |world: &mut World| snap_car(world, car, tick, transform));
+                }
+            }
+            ServerMessages::ProjectileSpawn {
+                entity,
+                translation,
+                velocity,
+                ..
+            } => {
+                projectiles.spawn(&mut cmd, entity, translation.into(), velocity.into());
+            }
+            ServerMessages::ProjectileDespawn { entity } => {
+                projectiles.despawn(&mut cmd, entity);
+            }
+            // Shown as the disconnect reason once the server drops the connection
+            ServerMessages::Rejected { reason } => {
+                errors.push(format!("rejected by server: {}", reason));
+            }
+        }
+    }
+
+    while let Some(message) = client.receive_message(ServerChannel::NetworkedEntities) {
+        if !matches!(*compatibility, ServerCompatibility::Compatible) {
+            continue;
+        }
+        let networked_entities: NetworkedEntities = match decode_server_message(&message) {
+            Ok(networked_entities) => networked_entities,
+            Err(e) => {
+                errors.push(format!("snapshot dropped: {}", e));
+                continue;
+            }
+        };
+        if let Err(e) = networked_entities.validate() {
+            errors.push(format!("snapshot dropped: {}", e));
+            continue;
+        }
+        // Unreliable channel, an older snapshot may arrive after a newer one
+        if networked_entities.tick <= clock.last_tick {
+            continue;
+        }
+        let baseline = networked_entities
+            .baseline_tick
+            .and_then(|baseline_tick| snapshot_history.get(baseline_tick));
+        let state = match networked_entities.decode(baseline) {
+            Ok(state) => state,
+            Err(e) => {
+                errors.push(format!("snapshot dropped: {}", e));
+                continue;
+            }
+        };
+        let tick = networked_entities.tick;
+        clock.last_tick = tick;
+
+        let applied_sequence = networked_entities.ack_input;
+        while let Some(&(sequence, ack_tick)) = clock.pending_inputs.front() {
+            if sequence > applied_sequence {
+                break;
+            }
+            if sequence == applied_sequence {
+                clock.rtt_ticks = Some(tick.wrapping_sub(ack_tick));
+            }
+            clock.pending_inputs.pop_front();
+        }
+
+        let controlled = lobby.players.get(&client_id).map(|info| info.server_entity);
+        for (server_entity, entity_state) in state.iter() {
+            let Some(entity) = network_mapping.0.get(server_entity) else {
+                continue;
+            };
+            // Wheels decode against the rest pose of our own copy of the car
+            let Ok((anchors, buffer)) = cars.get_mut(*entity) else {
+                continue;
+            };
+            let snapshot = CarSnapshot::new(tick, entity_state, anchors);
+            // The controlled car is predicted locally, see `reconcile_system`
+            if Some(*server_entity) == controlled {
+                authoritative.0 = Some(AuthoritativeCar {
+                    sequence: applied_sequence,
+                    translation: snapshot.translation,
+                    rotation: snapshot.rotation,
+                    wheels: snapshot.wheels,
+                    velocity: networked_entities
+                        .velocity
+                        .map(|(linvel, angvel)| Velocity {
+                            linvel: linvel.into(),
+                            angvel: angvel.into(),
+                        }),
+                });
+                continue;
+            }
+            // Not due by distance, the decoded state is stale so the last update stays.
+            // Cars left out as unchanged get it again, so one that stopped settles where it is.
+            if networked_entities.held_back.contains(server_entity) {
+                continue;
+            }
+            if let Some(mut buffer) = buffer {
+                buffer.push(snapshot);
+            }
+        }
+        snapshot_history.insert(tick, state);
+    }
+}
@@ -0,0 +1,70 @@
+//! Player names floating above remote cars, in the color each player picked.
+use bevy::prelude::*;
+use bevy_garage_arena_lib::PlayerProfile;
+
+/// Height above the car's origin where the name is anchored.
+const NAMEPLATE_HEIGHT: f32 = 1.8;
+
+/// UI text following `car` on screen, despawned along with it.
+#[derive(Debug, Component)]
+pub struct Nameplate {
+    car: Entity,
+}
+
+pub fn spawn_nameplate(cmd: &mut Commands, car: Entity, profile: &PlayerProfile) {
+    let [r, g, b] = profile.color;
+    cmd.spawn((
+        TextBundle::from_section(
+            profile.name.clone(),
+            TextStyle {
+                font_size: 18.,
+                color: Color::rgb_u8(r, g, b),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            ..default()
+        }),
+        Nameplate { car },
+    ));
+}
+
+fn nameplate_system(
+    mut cmd: Commands,
+    cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    cars: Query<&GlobalTransform>,
+    mut nameplates: Query<(Entity, &Nameplate, &Node, &mut Style, &mut Visibility)>,
+) {
+    let Some((camera, camera_transform)) = cameras.iter().find(|(camera, _)| camera.is_active)
+    else {
+        return;
+    };
+    for (entity, nameplate, node, mut style, mut visibility) in nameplates.iter_mut() {
+        let Ok(car) = cars.get(nameplate.car) else {
+            cmd.entity(entity).despawn_recursive();
+            continue;
+        };
+        let anchor = car.translation() + Vec3::Y * NAMEPLATE_HEIGHT;
+        // Behind the camera or off screen
+        let Some(position) = camera.world_to_viewport(camera_transform, anchor) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        let size = node.size();
+        style.left = Val::Px(position.x - size.x / 2.);
+        style.top = Val::Px(position.y - size.y);
+        *visibility = Visibility::Inherited;
+    }
+}
+
+pub struct NameplatePlugin;
+
+impl Plugin for NameplatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            nameplate_system.after(TransformSystem::TransformPropagate),
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
+use bevy_garage_arena_lib::{
+    apply_player_input, physics_settings_system, PhysicsSettings, PlayerInput, DEFAULT_TICK_RATE,
+};
+use bevy_garage_car::{esp_system, Car, CarWheels, Wheel};
+use bevy_rapier3d::prelude::*;
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{interpolation::SnapshotBuffer, ControlledPlayer, NetworkClock};
+
+/// Server position beyond which the controlled car is rewound and inputs replayed.
+const RECONCILE_DISTANCE: f32 = 0.1;
+/// Same for rotation, radians.
+const RECONCILE_ANGLE: f32 = 0.05;
+const MAX_HISTORY: usize = 256;
+
+/// One physics step of the local simulation, run once per fixed tick
+/// and again for every replayed input during reconciliation.
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PredictionStep;
+
+#[derive(Debug)]
+struct PredictedInput {
+    input: PlayerInput,
+    translation: Vec3,
+    rotation: Quat,
+}
+
+/// Inputs not yet confirmed by the server with the car state each one predicted.
+#[derive(Debug, Default, Resource)]
+pub struct PredictionHistory(VecDeque<PredictedInput>);
+
+impl PredictionHistory {
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AuthoritativeCar {
+    pub sequence: u32,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub wheels: [(Vec3, Quat); 4],
+    pub velocity: Option<Velocity>,
+}
+
+/// Newest server state of the controlled car, consumed by `reconcile_system`.
+#[derive(Debug, Default, Resource)]
+pub struct AuthoritativeState(pub Option<AuthoritativeCar>);
+
+/// Simulates the controlled car locally with the server's physics and level,
+/// remote cars are kinematic and only follow snapshots.
+pub struct PredictionPlugin;
+
+impl Plugin for PredictionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(DEFAULT_TICK_RATE as f64));
+        app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default().in_schedule(PredictionStep));
+        app.insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed {
+                dt: 1. / DEFAULT_TICK_RATE as f32,
+                substeps: 5,
+            },
+            ..default()
+        });
+        app.add_systems(PredictionStep, esp_system.before(PhysicsSet::SyncBackend));
+        app.insert_resource(PredictionHistory::default());
+        app.insert_resource(AuthoritativeState::default());
+        // Defaults until `ServerInfo` brings the server's
+        app.insert_resource(PhysicsSettings::default());
+        app.add_systems(Update, physics_settings_system);
+        app.add_systems(PostUpdate, remote_cars_kinematic_system);
+        app.add_systems(Update, tick_rate_system);
+    }
+}
+
+/// Runs prediction at the server's tick rate once `ServerInfo` tells it.
+fn tick_rate_system(
+    clock: Res<NetworkClock>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    let timestep = Duration::from_secs_f64(1. / clock.tick_rate as f64);
+    if fixed_time.timestep() == timestep {
+        return;
+    }
+    fixed_time.set_timestep(timestep);
+    rapier_config.timestep_mode = TimestepMode::Fixed {
+        dt: timestep.as_secs_f32(),
+        substeps: 5,
+    };
+}
+
+fn remote_cars_kinematic_system(
+    mut cmd: Commands,
+    cars: Query<(Entity, &CarWheels), (Added<CarWheels>, Without<ControlledPlayer>)>,
+) {
+    for (entity, wheels) in cars.iter() {
+        cmd.entity(entity).insert(RigidBody::KinematicPositionBased);
+        for wheel in wheels.entities {
+            cmd.entity(wheel).insert(RigidBody::KinematicPositionBased);
+        }
+    }
+}
+
+fn controlled_car_transform(world: &mut World) -> Option<(Vec3, Quat)> {
+    let mut query = world.query_filtered::<&Transform, With<ControlledPlayer>>();
+    query
+        .get_single(world)
+        .ok()
+        .map(|t| (t.translation, t.rotation))
+}
+
+/// Applies `input` to the controlled car, if any, and advances physics one step.
+fn step(world: &mut World, input: &PlayerInput) {
+    let mut query = world.query_filtered::<&mut Car, With<ControlledPlayer>>();
+    if let Ok(mut car) = query.get_single_mut(world) {
+        apply_player_input(input, &mut car);
+    }
+    world.run_schedule(PredictionStep);
+}
+
+/// Runs each fixed tick right after the input for it was sent.
+pub fn prediction_step_system(world: &mut World) {
+    let input = *world.resource::<PlayerInput>();
+    step(world, &input);
+    let Some((translation, rotation)) = controlled_car_transform(world) else {
+        return;
+    };
+    let mut history = world.resource_mut::<PredictionHistory>();
+    if history.0.len() == MAX_HISTORY {
+        history.0.pop_front();
+    }
+    history.0.push_back(PredictedInput {
+        input,
+        translation,
+        rotation,
+    });
+}
+
+fn snap_controlled_car(world: &mut World, server: &AuthoritativeCar) -> bool {
+    let mut query =
+        world.query_filtered::<(Entity, &mut Transform, &CarWheels), With<ControlledPlayer>>();
+    let Ok((car, mut transform, wheels)) = query.get_single_mut(world) else {
+        return false;
+    };
+    transform.translation = server.translation;
+    transform.rotation = server.rotation;
+    let wheels = wheels.entities;
+    let mut wheel_query = world.query_filtered::<&mut Transform, With<Wheel>>();
+    for (i, wheel) in wheels.iter().enumerate() {
+        if let Ok(mut wheel_transform) = wheel_query.get_mut(world, *wheel) {
+            wheel_transform.translation = server.wheels[i].0;
+            wheel_transform.rotation = server.wheels[i].1;
+        }
+    }
+    // Otherwise the replay starts from the mispredicted motion
+    let Some(velocity) = server.velocity else {
+        return true;
+    };
+    if let Some(mut car_velocity) = world.get_mut::<Velocity>(car) {
+        *car_velocity = velocity;
+    }
+    // Wheels move with the body, their spin is kept
+    for (i, wheel) in wheels.iter().enumerate() {
+        if let Some(mut wheel_velocity) = world.get_mut::<Velocity>(*wheel) {
+            wheel_velocity.linvel =
+                velocity.linear_velocity_at_point(server.wheels[i].0, server.translation);
+        }
+    }
+    true
+}
+
+/// Moves a car the server respawned at `tick`, wheels along and at rest,
+/// without interpolating or replaying inputs across the jump.
+pub fn snap_car(world: &mut World, car: Entity, tick: u32, to: Transform) {
+    let Some(from) = world.get::<Transform>(car).copied() else {
+        return;
+    };
+    let to_spawn = to.compute_matrix() * from.compute_matrix().inverse();
+    let wheels = world.get::<CarWheels>(car).map(|wheels| wheels.entities);
+    for wheel in wheels.into_iter().flatten() {
+        if let Some(mut transform) = world.get_mut::<Transform>(wheel) {
+            *transform = Transform::from_matrix(to_spawn * transform.compute_matrix());
+        }
+        if let Some(mut velocity) = world.get_mut::<Velocity>(wheel) {
+            *velocity = Velocity::zero();
+        }
+    }
+    if let Some(mut transform) = world.get_mut::<Transform>(car) {
+        *transform = to;
+    }
+    if let Some(mut velocity) = world.get_mut::<Velocity>(car) {
+        *velocity = Velocity::zero();
+    }
+    if let Some(mut buffer) = world.get_mut::<SnapshotBuffer>(car) {
+        buffer.reset(tick);
+    }
+    if world.get::<ControlledPlayer>(car).is_some() {
+        world.resource_mut::<PredictionHistory>().clear();
+        world.resource_mut::<AuthoritativeState>().0 = None;
+    }
+}
+
+/// Compares the server state with what was predicted for the same input,
+/// on divergence snaps the car to the server and replays newer inputs.
+pub fn reconcile_system(world: &mut World) {
+    let Some(server) = world.resource_mut::<AuthoritativeState>().0.take() else {
+        return;
+    };
+    let mut history = std::mem::take(&mut world.resource_mut::<PredictionHistory>().0);
+    while history
+        .front()
+        .is_some_and(|p| p.input.sequence < server.sequence)
+    {
+        history.pop_front();
+    }
+    let predicted = match history.front() {
+        Some(p) if p.input.sequence == server.sequence => {
+            let p = history.pop_front().unwrap();
+            Some((p.translation, p.rotation))
+        }
+        Some(_) => None,
+        None => controlled_car_transform(world),
+    };
+    let diverged = match predicted {
+        Some((translation, rotation)) => {
+            translation.distance(server.translation) > RECONCILE_DISTANCE
+                || rotation.angle_between(server.rotation) > RECONCILE_ANGLE
+        }
+        None => true,
+    };
+
+    if diverged && snap_controlled_car(world, &server) {
+        for predicted in history.iter_mut() {
+            step(world, &predicted.input);
+            if let Some((translation, rotation)) = controlled_car_transform(world) {
+                predicted.translation = translation;
+                predicted.rotation = rotation;
+            }
+        }
+    }
+    world.resource_mut::<PredictionHistory>().0 = history;
+}
@@ -0,0 +1,131 @@
+//! Firing `PlayerCommand::BasicAttack` and showing the server's projectiles,
+//! which fly straight so they're moved locally between spawn and despawn.
+use bevy::prelude::*;
+use bevy_garage_arena_lib::PlayerCommand;
+use std::collections::HashMap;
+
+use crate::{
+    bindings::{Action, ActionState},
+    connection::ClientState,
+    ControlledPlayer,
+};
+
+/// How far ahead of the car `cast_at` is, the server only uses the direction.
+const AIM_DISTANCE: f32 = 100.;
+/// Matches the server's muzzle, so the shot leaves level with the camera's heading.
+const AIM_HEIGHT: f32 = 1.;
+
+#[derive(Debug, Component)]
+struct ClientProjectile {
+    velocity: Vec3,
+}
+
+/// Projectiles by server entity, with what they're drawn with.
+#[derive(Debug, Resource)]
+pub struct Projectiles {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+    entities: HashMap<Entity, Entity>,
+}
+
+impl FromWorld for Projectiles {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Sphere::new(0.25).mesh().uv(16, 8));
+        let material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: Color::ORANGE_RED,
+                emissive: Color::rgb_linear(8., 2., 0.5),
+                ..default()
+            });
+        Self {
+            mesh,
+            material,
+            entities: HashMap::new(),
+        }
+    }
+}
+
+impl Projectiles {
+    pub fn spawn(
+        &mut self,
+        cmd: &mut Commands,
+        server_entity: Entity,
+        translation: Vec3,
+        velocity: Vec3,
+    ) {
+        let entity = cmd
+            .spawn((
+                PbrBundle {
+                    mesh: self.mesh.clone(),
+                    material: self.material.clone(),
+                    transform: Transform::from_translation(translation),
+                    ..default()
+                },
+                ClientProjectile { velocity },
+            ))
+            .id();
+        self.entities.insert(server_entity, entity);
+    }
+
+    pub fn despawn(&mut self, cmd: &mut Commands, server_entity: Entity) {
+        if let Some(entity) = self.entities.remove(&server_entity) {
+            cmd.entity(entity).despawn();
+        }
+    }
+
+    /// Client entities of every projectile, for when the session ends.
+    pub fn clear(&mut self) -> Vec<Entity> {
+        self.entities.drain().map(|(_, entity)| entity).collect()
+    }
+}
+
+/// Shoots where the camera looks, flattened so the shot doesn't hit the ground.
+fn fire_system(
+    actions: Res<ActionState>,
+    cars: Query<&Transform, With<ControlledPlayer>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    mut player_commands: EventWriter<PlayerCommand>,
+) {
+    if !actions.just_pressed(Action::Fire) {
+        return;
+    }
+    let Ok(car) = cars.get_single() else {
+        return;
+    };
+    let Some((_, camera)) = cameras.iter().find(|(camera, _)| camera.is_active) else {
+        return;
+    };
+    let Some(aim) = (Vec3::from(camera.forward()) * Vec3::new(1., 0., 1.)).try_normalize() else {
+        return;
+    };
+    player_commands.send(PlayerCommand::BasicAttack {
+        cast_at: car.translation + Vec3::Y * AIM_HEIGHT + aim * AIM_DISTANCE,
+    });
+}
+
+fn projectile_motion_system(
+    time: Res<Time>,
+    mut projectiles: Query<(&ClientProjectile, &mut Transform)>,
+) {
+    for (projectile, mut transform) in projectiles.iter_mut() {
+        transform.translation += projectile.velocity * time.delta_seconds();
+    }
+}
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Projectiles>();
+        app.add_systems(
+            Update,
+            (
+                fire_system.run_if(in_state(ClientState::InGame)),
+                projectile_motion_system,
+            ),
+        );
+    }
+}
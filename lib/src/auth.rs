@@ -0,0 +1,177 @@
+use bevy::prelude::Resource;
+use bevy_renet::renet::transport::{
+    ConnectToken, TokenGenerationError, NETCODE_KEY_BYTES, NETCODE_USER_DATA_BYTES,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{memory::HOST_CLIENT_ID, profile::UserData, PROTOCOL_ID};
+
+pub type PrivateKey = [u8; NETCODE_KEY_BYTES];
+
+/// Opt-in for `ServerAuthentication::Unsecure` / `ClientAuthentication::Unsecure`, dev only.
+pub fn unsecure_from_env() -> bool {
+    matches!(
+        std::env::var("RENET_UNSECURE").as_deref(),
+        Ok("1") | Ok("true")
+    )
+}
+
+/// Loads the netcode private key from `RENET_PRIVATE_KEY` (64 hex chars)
+/// or from the file at `RENET_PRIVATE_KEY_FILE` (same format).
+pub fn private_key_from_env() -> Result<Option<PrivateKey>, String> {
+    let hex = if let Ok(hex) = std::env::var("RENET_PRIVATE_KEY") {
+        hex
+    } else if let Ok(path) = std::env::var("RENET_PRIVATE_KEY_FILE") {
+        std::fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read private key file {}: {}", path, e))?
+    } else {
+        return Ok(None);
+    };
+    parse_private_key(&hex).map(Some)
+}
+
+pub fn parse_private_key(hex: &str) -> Result<PrivateKey, String> {
+    let hex = hex.trim();
+    if hex.len() != NETCODE_KEY_BYTES * 2 {
+        return Err(format!(
+            "private key must be {} hex chars, got {}",
+            NETCODE_KEY_BYTES * 2,
+            hex.len()
+        ));
+    }
+    let mut key = [0u8; NETCODE_KEY_BYTES];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("private key has non-hex chars at {}", i * 2))?;
+    }
+    Ok(key)
+}
+
+/// Fresh id for a netcode client. Random so that clients picking their own rarely collide,
+/// `serve_tokens` re-rolls ids in use and the server rejects a duplicate on connect.
+pub fn random_client_id() -> u64 {
+    fastrand::u64(HOST_CLIENT_ID + 1..)
+}
+
+/// Ids of the clients connected to the server, shared with the token service.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct ConnectedClients(Arc<Mutex<HashSet<u64>>>);
+
+impl ConnectedClients {
+    pub fn insert(&self, client_id: u64) {
+        self.0.lock().unwrap().insert(client_id);
+    }
+
+    pub fn remove(&self, client_id: u64) {
+        self.0.lock().unwrap().remove(&client_id);
+    }
+
+    pub fn contains(&self, client_id: u64) -> bool {
+        self.0.lock().unwrap().contains(&client_id)
+    }
+}
+
+/// Issues netcode connect tokens signed with the server private key.
+/// Used in-process by a client that knows the key, or behind `serve_tokens`.
+#[derive(Clone)]
+pub struct TokenIssuer {
+    pub private_key: PrivateKey,
+    pub server_addresses: Vec<SocketAddr>,
+    pub expire_seconds: u64,
+    pub timeout_seconds: i32,
+}
+
+impl TokenIssuer {
+    pub fn new(private_key: PrivateKey, server_addresses: Vec<SocketAddr>) -> Self {
+        Self {
+            private_key,
+            server_addresses,
+            expire_seconds: 300,
+            timeout_seconds: 15,
+        }
+    }
+
+    pub fn issue(
+        &self,
+        client_id: u64,
+        user_data: Option<&[u8; NETCODE_USER_DATA_BYTES]>,
+    ) -> Result<ConnectToken, TokenGenerationError> {
+        let current_time: Duration = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        ConnectToken::generate(
+            current_time,
+            PROTOCOL_ID,
+            self.expire_seconds,
+            client_id,
+            self.timeout_seconds,
+            self.server_addresses.clone(),
+            user_data,
+            &self.private_key,
+        )
+    }
+}
+
+/// Minimal stand-in token service: each TCP connection sends its `user_data`
+/// and receives a serialized `ConnectToken` for a client id the service picks,
+/// never one that's `connected` or in a token that may still be used.
+/// Blocks forever, run it on its own thread.
+pub fn serve_tokens(listener: TcpListener, issuer: TokenIssuer, connected: ConnectedClients) {
+    let mut issued: HashMap<u64, Instant> = HashMap::new();
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("token service accept failed: {}", e);
+                continue;
+            }
+        };
+        let expire = Duration::from_secs(issuer.expire_seconds);
+        issued.retain(|_, issued_at| issued_at.elapsed() < expire);
+        let client_id = loop {
+            let client_id = random_client_id();
+            if !connected.contains(client_id) && !issued.contains_key(&client_id) {
+                break client_id;
+            }
+        };
+        match handle_token_request(&mut stream, &issuer, client_id) {
+            Ok(()) => {
+                issued.insert(client_id, Instant::now());
+            }
+            Err(e) => println!("token service request failed: {}", e),
+        }
+    }
+}
+
+fn handle_token_request(
+    stream: &mut TcpStream,
+    issuer: &TokenIssuer,
+    client_id: u64,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut user_data = [0u8; NETCODE_USER_DATA_BYTES];
+    stream.read_exact(&mut user_data)?;
+    let token = issuer
+        .issue(client_id, Some(&user_data))
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    token.write(stream)?;
+    stream.flush()
+}
+
+/// Client side of `serve_tokens`, the token carries the assigned client id.
+pub fn request_token(
+    token_addr: SocketAddr,
+    user_data: &UserData,
+) -> std::io::Result<ConnectToken> {
+    let mut stream = TcpStream::connect_timeout(&token_addr, Duration::from_secs(5))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.write_all(user_data)?;
+    ConnectToken::read(&mut stream)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+}
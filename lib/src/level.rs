@@ -0,0 +1,470 @@
+//! Levels described in RON files under `assets/levels`, see `assets/levels/arena.ron`.
+//! The headless server and the graphical client build the same colliders from them,
+//! only the client draws meshes and lights.
+use bevy::prelude::*;
+use bevy_garage_car::STATIC_GROUP;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fnv1a, protocol::MAX_SERVER_MESSAGE_BYTES, ServerMessages, SpawnPoint, Wall, LEVEL_HEIGHT,
+    LEVEL_SIZE,
+};
+
+pub const DEFAULT_LEVEL: &str = "arena";
+/// Relative to the working directory, like the server's settings file.
+const LEVELS_DIR: &str = "assets/levels";
+/// Used when there's no file of that name, e.g. on the web or when run from another directory.
+const BUILTIN_LEVELS: [(&str, &str); 1] =
+    [(DEFAULT_LEVEL, include_str!("../../assets/levels/arena.ron"))];
+
+/// Everything `setup_level` spawned, replaced when the level changes.
+#[derive(Debug, Component)]
+pub struct LevelEntity;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Resource)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    pub materials: Vec<LevelMaterial>,
+    pub colliders: Vec<LevelCollider>,
+    pub spawn_points: Vec<LevelSpawnPoint>,
+    #[serde(default)]
+    pub lights: Vec<LevelLight>,
+}
+
+/// Physics of a surface, and how it looks on visible colliders.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LevelMaterial {
+    pub name: String,
+    pub friction: f32,
+    pub restitution: f32,
+    pub color: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub textures: LevelTextures,
+    /// Texture repeats per mesh face.
+    pub uv_scale: f32,
+}
+
+impl Default for LevelMaterial {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            friction: 0.5,
+            restitution: 0.,
+            color: [1., 1., 1.],
+            metallic: 0.,
+            roughness: 0.5,
+            textures: LevelTextures::default(),
+            uv_scale: 1.,
+        }
+    }
+}
+
+/// Image paths in the assets directory.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LevelTextures {
+    pub normal: Option<String>,
+    pub metallic_roughness: Option<String>,
+    pub diffuse_transmission: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LevelShape {
+    Cuboid { half_extents: [f32; 3] },
+    Ball { radius: f32 },
+    Cylinder { half_height: f32, radius: f32 },
+}
+
+impl LevelShape {
+    pub fn collider(&self) -> Collider {
+        match *self {
+            Self::Cuboid {
+                half_extents: [x, y, z],
+            } => Collider::cuboid(x, y, z),
+            Self::Ball { radius } => Collider::ball(radius),
+            Self::Cylinder {
+                half_height,
+                radius,
+            } => Collider::cylinder(half_height, radius),
+        }
+    }
+
+    #[cfg(feature = "graphics")]
+    pub fn mesh(&self) -> Mesh {
+        match *self {
+            Self::Cuboid { half_extents } => {
+                Cuboid::from_size(Vec3::from(half_extents) * 2.).into()
+            }
+            Self::Ball { radius } => Sphere::new(radius).mesh().uv(32, 18),
+            Self::Cylinder {
+                half_height,
+                radius,
+            } => Cylinder::new(radius, half_height * 2.).into(),
+        }
+    }
+
+    fn dimensions(&self) -> Vec<f32> {
+        match *self {
+            Self::Cuboid { half_extents } => half_extents.to_vec(),
+            Self::Ball { radius } => vec![radius],
+            Self::Cylinder {
+                half_height,
+                radius,
+            } => vec![half_height, radius],
+        }
+    }
+
+    fn kind(&self) -> f32 {
+        match self {
+            Self::Cuboid { .. } => 0.,
+            Self::Ball { .. } => 1.,
+            Self::Cylinder { .. } => 2.,
+        }
+    }
+}
+
+/// A fixed collider, drawn by the client only when `visible`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelCollider {
+    pub shape: LevelShape,
+    pub translation: [f32; 3],
+    /// Euler angles in degrees around X, Y and Z.
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    /// Name of one of the level's materials.
+    pub material: String,
+    /// Cars are damaged hitting it, see `Wall`.
+    #[serde(default)]
+    pub wall: bool,
+    #[serde(default)]
+    pub visible: bool,
+}
+
+impl LevelCollider {
+    pub fn transform(&self) -> Transform {
+        let [x, y, z] = self.rotation.map(f32::to_radians);
+        Transform::from_translation(self.translation.into()).with_rotation(Quat::from_euler(
+            EulerRot::XYZ,
+            x,
+            y,
+            z,
+        ))
+    }
+}
+
+/// Where a car spawns, `yaw` in degrees with 0 facing -Z.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LevelSpawnPoint {
+    pub translation: [f32; 3],
+    #[serde(default)]
+    pub yaw: f32,
+}
+
+impl LevelSpawnPoint {
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(self.translation.into())
+            .with_rotation(Quat::from_rotation_y(self.yaw.to_radians()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LevelLight {
+    Directional {
+        direction: [f32; 3],
+        illuminance: f32,
+        shadows: bool,
+    },
+    Point {
+        translation: [f32; 3],
+        intensity: f32,
+        range: f32,
+        shadows: bool,
+    },
+}
+
+impl Level {
+    /// Reads `assets/levels/<name>.ron`, or the built-in level of that name if there's no such file.
+    pub fn load(name: &str) -> Result<Self, String> {
+        let path = format!("{}/{}.ron", LEVELS_DIR, name);
+        let level = match std::fs::read_to_string(&path) {
+            Ok(contents) => Self::from_ron(&contents)
+                .map_err(|e| format!("invalid level file {}: {}", path, e))?,
+            Err(e) => {
+                let Some((_, contents)) = BUILTIN_LEVELS.iter().find(|(n, _)| *n == name) else {
+                    return Err(format!("can't read level file {}: {}", path, e));
+                };
+                Self::from_ron(contents)
+                    .map_err(|e| format!("invalid built-in level {}: {}", name, e))?
+            }
+        };
+        Ok(level)
+    }
+
+    /// The level shipped with the game, for when the default one can't be loaded.
+    pub fn builtin() -> Self {
+        let (_, contents) = BUILTIN_LEVELS[0];
+        Self::from_ron(contents).expect("the built-in level is valid")
+    }
+
+    pub fn to_ron(&self) -> String {
+        ron::to_string(self).unwrap()
+    }
+
+    pub fn from_ron(contents: &str) -> Result<Self, String> {
+        let level: Self = ron::from_str(contents).map_err(|e| e.to_string())?;
+        level.validate()?;
+        Ok(level)
+    }
+
+    /// Collects every problem at once, like the server settings.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = vec![];
+        if self.colliders.is_empty() {
+            errors.push("no colliders".to_string());
+        }
+        if self.spawn_points.is_empty() {
+            errors.push("no spawn points".to_string());
+        }
+        for material in self.materials.iter() {
+            if !(0. ..).contains(&material.friction) || !(0. ..).contains(&material.restitution) {
+                errors.push(format!(
+                    "material '{}' friction and restitution can't be negative",
+                    material.name
+                ));
+            }
+        }
+        // Snapshots can't encode positions further out, see `quantize`
+        let in_bounds = |translation: &[f32; 3]| {
+            translation.iter().all(|v| v.is_finite())
+                && translation[0].abs() <= LEVEL_SIZE / 2.
+                && translation[2].abs() <= LEVEL_SIZE / 2.
+                && translation[1].abs() <= LEVEL_HEIGHT / 2.
+        };
+        // NaN would get through the rotation encoding and into the hash
+        let valid_rotation = |rotation: Quat| rotation.is_finite() && rotation.is_normalized();
+        for (i, collider) in self.colliders.iter().enumerate() {
+            if !self.materials.iter().any(|m| m.name == collider.material) {
+                errors.push(format!(
+                    "collider {} has unknown material '{}'",
+                    i, collider.material
+                ));
+            }
+            if collider
+                .shape
+                .dimensions()
+                .iter()
+                .any(|v| !v.is_finite() || *v <= 0.)
+            {
+                errors.push(format!("collider {} needs a size greater than 0", i));
+            }
+            if !in_bounds(&collider.translation) {
+                errors.push(format!(
+                    "collider {} is outside the {} by {} m level bounds",
+                    i, LEVEL_SIZE, LEVEL_HEIGHT
+                ));
+            }
+            if !valid_rotation(collider.transform().rotation) {
+                errors.push(format!("collider {} needs a finite rotation", i));
+            }
+        }
+        for (i, point) in self.spawn_points.iter().enumerate() {
+            if !in_bounds(&point.translation) {
+                errors.push(format!(
+                    "spawn point {} is outside the {} by {} m level bounds",
+                    i, LEVEL_SIZE, LEVEL_HEIGHT
+                ));
+            }
+            if !valid_rotation(point.transform().rotation) {
+                errors.push(format!("spawn point {} needs a finite yaw", i));
+            }
+        }
+        // Clients get the level in a single message, see `ServerMessages::Level`
+        let size = bincode::serialized_size(&ServerMessages::Level { ron: self.to_ron() })
+            .unwrap_or(u64::MAX);
+        if size > MAX_SERVER_MESSAGE_BYTES {
+            errors.push(format!(
+                "level of {} bytes exceeds the {} byte message limit",
+                size, MAX_SERVER_MESSAGE_BYTES
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+
+    pub fn material(&self, name: &str) -> Option<&LevelMaterial> {
+        self.materials.iter().find(|material| material.name == name)
+    }
+
+    /// Hash of what physics sees: collider shapes, poses, surfaces and walls, as written
+    /// in the file. Names, looks and lights don't count, nor spawn points only the server uses.
+    /// Compared in the `ProtocolVersion` handshake.
+    pub fn hash(&self) -> u64 {
+        let mut values = vec![];
+        for collider in self.colliders.iter() {
+            values.push(collider.shape.kind());
+            values.extend(collider.shape.dimensions());
+            values.extend(collider.translation);
+            values.extend(collider.rotation);
+            if let Some(material) = self.material(&collider.material) {
+                values.extend([material.friction, material.restitution]);
+            }
+            values.push(if collider.wall { 1. } else { 0. });
+        }
+        let mut bytes = vec![];
+        for v in values {
+            // -0 and 0 are the same place
+            bytes.extend_from_slice(&(v + 0.).to_le_bytes());
+        }
+        fnv1a(&bytes)
+    }
+}
+
+/// Spawns the level, first despawning the previous one if the client switched levels.
+pub fn setup_level(
+    mut cmd: Commands,
+    level: Res<Level>,
+    previous: Query<Entity, With<LevelEntity>>,
+    #[cfg(feature = "graphics")] mut meshes: ResMut<Assets<Mesh>>,
+    #[cfg(feature = "graphics")] mut materials: ResMut<Assets<StandardMaterial>>,
+    #[cfg(feature = "graphics")] asset_server: Res<AssetServer>,
+) {
+    for entity in previous.iter() {
+        cmd.entity(entity).despawn_recursive();
+    }
+    for collider in level.colliders.iter() {
+        // Validated on load
+        let material = level.material(&collider.material).unwrap();
+        let mut entity = cmd.spawn((
+            collider.shape.collider(),
+            RigidBody::Fixed,
+            ColliderScale::Absolute(Vec3::ONE),
+            CollisionGroups::new(STATIC_GROUP, Group::ALL),
+            Friction::coefficient(material.friction),
+            Restitution::coefficient(material.restitution),
+            TransformBundle::from_transform(collider.transform()),
+            LevelEntity,
+        ));
+        if collider.wall {
+            entity.insert(Wall);
+        }
+    }
+    for point in level.spawn_points.iter() {
+        cmd.spawn((
+            SpawnPoint,
+            TransformBundle::from_transform(point.transform()),
+            LevelEntity,
+        ));
+    }
+
+    #[cfg(feature = "graphics")]
+    {
+        use bevy::render::{
+            mesh::VertexAttributeValues,
+            texture::{
+                ImageAddressMode, ImageLoaderSettings, ImageSampler, ImageSamplerDescriptor,
+            },
+        };
+        use std::collections::HashMap;
+        let repeat = |settings: &mut ImageLoaderSettings| {
+            settings.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+                address_mode_u: ImageAddressMode::Repeat,
+                address_mode_v: ImageAddressMode::Repeat,
+                address_mode_w: ImageAddressMode::Repeat,
+                ..Default::default()
+            });
+        };
+        let mut handles: HashMap<&str, Handle<StandardMaterial>> = HashMap::new();
+        for collider in level.colliders.iter().filter(|collider| collider.visible) {
+            let material = level.material(&collider.material).unwrap();
+            let handle = handles
+                .entry(material.name.as_str())
+                .or_insert_with(|| {
+                    let textures = &material.textures;
+                    let load = |path: &Option<String>| {
+                        path.as_ref()
+                            .map(|path| asset_server.load_with_settings(path.clone(), repeat))
+                    };
+                    let [r, g, b] = material.color;
+                    materials.add(StandardMaterial {
+                        base_color: Color::rgb(r, g, b),
+                        normal_map_texture: load(&textures.normal),
+                        metallic: material.metallic,
+                        perceptual_roughness: material.roughness,
+                        metallic_roughness_texture: load(&textures.metallic_roughness),
+                        diffuse_transmission_texture: load(&textures.diffuse_transmission),
+                        ..default()
+                    })
+                })
+                .clone();
+            let mut mesh = collider.shape.mesh();
+            if let Some(VertexAttributeValues::Float32x2(uvs)) =
+                mesh.attribute_mut(Mesh::ATTRIBUTE_UV_0)
+            {
+                for uv in uvs.iter_mut() {
+                    uv[0] *= material.uv_scale;
+                    uv[1] *= material.uv_scale;
+                }
+            }
+            cmd.spawn((
+                PbrBundle {
+                    mesh: meshes.add(mesh),
+                    material: handle,
+                    transform: collider.transform(),
+                    ..default()
+                },
+                LevelEntity,
+            ));
+        }
+        for light in level.lights.iter() {
+            match *light {
+                LevelLight::Directional {
+                    direction,
+                    illuminance,
+                    shadows,
+                } => {
+                    cmd.spawn((
+                        DirectionalLightBundle {
+                            directional_light: DirectionalLight {
+                                illuminance,
+                                shadows_enabled: shadows,
+                                ..default()
+                            },
+                            transform: Transform::default().looking_to(direction.into(), Vec3::Y),
+                            ..default()
+                        },
+                        LevelEntity,
+                    ));
+                }
+                LevelLight::Point {
+                    translation,
+                    intensity,
+                    range,
+                    shadows,
+                } => {
+                    cmd.spawn((
+                        PointLightBundle {
+                            point_light: PointLight {
+                                intensity,
+                                range,
+                                shadows_enabled: shadows,
+                                ..default()
+                            },
+                            transform: Transform::from_translation(translation.into()),
+                            ..default()
+                        },
+                        LevelEntity,
+                    ));
+                }
+            }
+        }
+    }
+}
@@ -1,31 +1,211 @@
 use bevy::prelude::*;
-use bevy_garage_car::STATIC_GROUP;
+use bevy_garage_car::Car;
 use bevy_rapier3d::prelude::*;
-use bevy_renet::renet::{
-    transport::NETCODE_KEY_BYTES, ChannelConfig, ClientId, ConnectionConfig, SendType,
-};
+use bevy_renet::renet::{ChannelConfig, ClientId, ConnectionConfig, SendType};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
-pub const PRIVATE_KEY: &[u8; NETCODE_KEY_BYTES] = b"an example very very secret key."; // 32-bytes
+#[macro_use]
+mod version;
+pub mod auth;
+pub mod level;
+pub mod memory;
+pub mod profile;
+pub mod protocol;
+pub mod quantize;
+pub mod snapshot;
+
+pub use level::{setup_level, Level, LevelEntity};
+pub use protocol::ProtocolError;
+pub use version::{fnv1a, ProtocolVersion};
+
 pub const PROTOCOL_ID: u64 = 7;
+/// Server ticks per second unless configured otherwise, see `ServerMessages::ServerInfo`.
+pub const DEFAULT_TICK_RATE: u32 = 60;
 
 #[derive(Debug, Component)]
 pub struct Player {
     pub id: ClientId,
+    pub profile: PlayerProfile,
 }
 
-#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Component, Resource)]
-pub struct PlayerInput {
-    pub up: bool,
-    pub down: bool,
-    pub left: bool,
-    pub right: bool,
+/// Level collider cars can be damaged against, see `LevelCollider::wall`.
+#[derive(Debug, Component)]
+pub struct Wall;
+
+/// Marker of a place cars spawn at, its `Transform` is the car's, see `Level::spawn_points`.
+#[derive(Debug, Component)]
+pub struct SpawnPoint;
+
+/// Server simulation tick, stamped on every `NetworkedEntities` snapshot.
+#[derive(Debug, Default, Clone, Copy, Resource)]
+pub struct ServerTick(pub u32);
+
+protocol_schema! {
+    #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Component, Resource)]
+    pub struct PlayerInput {
+        // Increments with every input sent, the server drops anything not newer than the last one
+        pub sequence: u32,
+        // Tick of the newest snapshot the client had when sending this input
+        pub ack_tick: u32,
+        // Analog controls, see `quantize` for the encoding
+        pub throttle: u8,
+        pub brake: u8,
+        pub steering: i8,
+        pub handbrake: bool,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Component, Event)]
+    pub enum PlayerCommand {
+        BasicAttack { cast_at: Vec3 },
+        // Puts the car back on a spawn point, e.g. when it's stuck on its roof
+        Respawn,
+    }
+
+    // Chosen by the player when connecting, see `profile`
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct PlayerProfile {
+        pub name: String,
+        pub color: [u8; 3],
+        pub car: CarModel,
+    }
+
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum CarModel {
+        #[default]
+        Race,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Component)]
+    pub enum ServerMessages {
+        // Must stay the first variant, see `ProtocolVersion`.
+        Handshake(ProtocolVersion),
+        // Right after the handshake, the server's level for clients with another one, see `level`
+        Level {
+            ron: String,
+        },
+        ServerInfo {
+            tick_rate: u32,
+            physics: PhysicsSettings,
+            max_health: f32,
+        },
+        PlayerCreate {
+            entity: Entity,
+            id: ClientId,
+            translation: [f32; 3],
+            rotation: [f32; 4],
+            profile: PlayerProfile,
+        },
+        PlayerRemove {
+            id: ClientId,
+        },
+        // Flies straight at `velocity` until despawned, only the server simulates hits
+        ProjectileSpawn {
+            entity: Entity,
+            owner: ClientId,
+            translation: [f32; 3],
+            velocity: [f32; 3],
+        },
+        ProjectileDespawn {
+            entity: Entity,
+        },
+        // After damage or a respawn, out of `ServerInfo::max_health`
+        PlayerHealth {
+            id: ClientId,
+            health: f32,
+        },
+        // `by` fired the last projectile, if that's what destroyed the car
+        PlayerDestroyed {
+            id: ClientId,
+            by: Option<ClientId>,
+        },
+        // The car was moved at `tick`, clients snap to it instead of interpolating
+        PlayerRespawn {
+            id: ClientId,
+            tick: u32,
+            translation: [f32; 3],
+            rotation: [f32; 4],
+        },
+        // Sent before the server disconnects a client it won't let in
+        Rejected {
+            reason: String,
+        },
+    }
+
+    // Wheel relative to its rest pose on the car, see `quantize`
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct WheelState {
+        pub suspension: i16,
+        pub steer: u16,
+        pub camber: u16,
+        pub spin: u16,
+    }
+
+    // Rapier integration parameters, the client predicts with the server's
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Resource)]
+    #[serde(default)]
+    pub struct PhysicsSettings {
+        pub solver_iterations: usize,
+        pub internal_pgs_iterations: usize,
+        pub additional_friction_iterations: usize,
+        pub erp: f32,
+    }
+
+    // Quantized car transform, see `quantize` for the encoding
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct EntityState {
+        pub translation: u64,
+        pub rotation: u32,
+        pub wheels: [WheelState; 4],
+    }
+
+    // Sent to each client separately, see `snapshot` for delta encoding
+    #[derive(Debug, Serialize, Deserialize, Default)]
+    pub struct NetworkedEntities {
+        pub tick: u32,
+        // Snapshot tick this one is a delta against, `None` for a full snapshot
+        pub baseline_tick: Option<u32>,
+        // Last input sequence applied to the receiving client's car
+        pub ack_input: u32,
+        // Linear and angular velocity of the receiving client's car, prediction replays from it
+        pub velocity: Option<([f32; 3], [f32; 3])>,
+        // Entities new or changed since the baseline
+        pub entities: Vec<Entity>,
+        pub states: Vec<EntityState>,
+        // Entities in the baseline that are gone
+        pub removed: Vec<Entity>,
+        // Entities that changed but weren't due an update, the rest left out are unchanged
+        pub held_back: Vec<Entity>,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Component, Event)]
-pub enum PlayerCommand {
-    BasicAttack { cast_at: Vec3 },
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self {
+            solver_iterations: 4,
+            internal_pgs_iterations: 48,
+            additional_friction_iterations: 4,
+            erp: 0.99,
+        }
+    }
+}
+
+impl NetworkedEntities {
+    /// Per-entity vectors must line up, the client indexes them together.
+    pub fn validate(&self) -> Result<(), ProtocolError> {
+        if self.states.len() != self.entities.len() {
+            return Err(ProtocolError::Invalid("networked entities length mismatch"));
+        }
+        Ok(())
+    }
+}
+
+/// Maps input onto car controls, shared by the server simulation and client prediction.
+pub fn apply_player_input(input: &PlayerInput, car: &mut Car) {
+    car.gas = input.throttle();
+    // The car has no separate handbrake, it brakes fully instead
+    car.brake = if input.handbrake { 1. } else { input.brake() };
+    car.steering = input.steering();
 }
 
 pub enum ClientChannel {
@@ -38,27 +218,6 @@ pub enum ServerChannel {
     NetworkedEntities,
 }
 
-#[derive(Debug, Serialize, Deserialize, Component)]
-pub enum ServerMessages {
-    PlayerCreate {
-        entity: Entity,
-        id: ClientId,
-        translation: [f32; 3],
-    },
-    PlayerRemove {
-        id: ClientId,
-    },
-}
-
-#[derive(Debug, Serialize, Deserialize, Default)]
-pub struct NetworkedEntities {
-    pub entities: Vec<Entity>,
-    pub translations: Vec<[f32; 3]>,
-    pub rotations: Vec<[f32; 4]>,
-    pub wheels_translations: Vec<[[f32; 3]; 4]>,
-    pub wheels_rotations: Vec<[[f32; 4]; 4]>,
-}
-
 impl From<ClientChannel> for u8 {
     fn from(channel_id: ClientChannel) -> Self {
         match channel_id {
@@ -69,18 +228,18 @@ impl From<ClientChannel> for u8 {
 }
 
 impl ClientChannel {
-    pub fn channels_config() -> Vec<ChannelConfig> {
+    pub fn channels_config(max_memory_usage_bytes: usize) -> Vec<ChannelConfig> {
         vec![
             ChannelConfig {
                 channel_id: Self::Input.into(),
-                max_memory_usage_bytes: 5 * 1024 * 1024,
+                max_memory_usage_bytes,
                 send_type: SendType::ReliableOrdered {
                     resend_time: Duration::ZERO,
                 },
             },
             ChannelConfig {
                 channel_id: Self::Command.into(),
-                max_memory_usage_bytes: 5 * 1024 * 1024,
+                max_memory_usage_bytes,
                 send_type: SendType::ReliableOrdered {
                     resend_time: Duration::ZERO,
                 },
@@ -99,16 +258,16 @@ impl From<ServerChannel> for u8 {
 }
 
 impl ServerChannel {
-    pub fn channels_config() -> Vec<ChannelConfig> {
+    pub fn channels_config(max_memory_usage_bytes: usize) -> Vec<ChannelConfig> {
         vec![
             ChannelConfig {
                 channel_id: Self::NetworkedEntities.into(),
-                max_memory_usage_bytes: 10 * 1024 * 1024,
+                max_memory_usage_bytes,
                 send_type: SendType::Unreliable,
             },
             ChannelConfig {
                 channel_id: Self::ServerMessages.into(),
-                max_memory_usage_bytes: 10 * 1024 * 1024,
+                max_memory_usage_bytes,
                 send_type: SendType::ReliableOrdered {
                     resend_time: Duration::from_millis(200),
                 },
@@ -117,136 +276,63 @@ impl ServerChannel {
     }
 }
 
+/// Bandwidth and per-channel memory limits of a renet connection.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkLimits {
+    pub available_bytes_per_tick: u64,
+    pub client_channel_memory_bytes: usize,
+    pub server_channel_memory_bytes: usize,
+    /// Per client snapshot size, about one packet, cars beyond it wait for a later snapshot.
+    pub snapshot_bytes: usize,
+}
+
+impl Default for NetworkLimits {
+    fn default() -> Self {
+        Self {
+            available_bytes_per_tick: 1024 * 1024,
+            client_channel_memory_bytes: 5 * 1024 * 1024,
+            server_channel_memory_bytes: 10 * 1024 * 1024,
+            snapshot_bytes: 1200,
+        }
+    }
+}
+
+impl NetworkLimits {
+    pub fn connection_config(&self) -> ConnectionConfig {
+        ConnectionConfig {
+            available_bytes_per_tick: self.available_bytes_per_tick,
+            client_channels_config: ClientChannel::channels_config(
+                self.client_channel_memory_bytes,
+            ),
+            server_channels_config: ServerChannel::channels_config(
+                self.server_channel_memory_bytes,
+            ),
+        }
+    }
+}
+
 pub fn connection_config() -> ConnectionConfig {
-    ConnectionConfig {
-        available_bytes_per_tick: 1024 * 1024,
-        client_channels_config: ClientChannel::channels_config(),
-        server_channels_config: ServerChannel::channels_config(),
-    }
-}
-
-pub fn setup_level(
-    mut cmd: Commands,
-    #[cfg(feature = "graphics")] mut meshes: ResMut<Assets<Mesh>>,
-    #[cfg(feature = "graphics")] mut materials: ResMut<Assets<StandardMaterial>>,
-    #[cfg(feature = "graphics")] asset_server: Res<AssetServer>,
-) {
-    let size = 1000.;
-    let size_half = size / 2.;
-    let t0 = Vec3::new(0., 0., 0.);
-    let cuboid = Collider::cuboid(size_half, 0.5, size_half);
-    cmd.spawn((
-        cuboid,
-        RigidBody::Fixed,
-        ColliderScale::Absolute(Vec3::ONE),
-        CollisionGroups::new(STATIC_GROUP, Group::ALL),
-        Friction::coefficient(3.),
-        Restitution::coefficient(0.),
-        TransformBundle::from_transform(Transform::from_translation(t0 + Vec3::new(0., -0.5, 0.))),
-    ));
-
-    let wall_x = Collider::cuboid(size_half, 10., 1.);
-    cmd.spawn((
-        wall_x.clone(),
-        RigidBody::Fixed,
-        ColliderScale::Absolute(Vec3::ONE),
-        CollisionGroups::new(STATIC_GROUP, Group::ALL),
-        Friction::coefficient(0.5),
-        Restitution::coefficient(0.),
-        TransformBundle::from_transform(Transform::from_translation(
-            t0 + Vec3::new(0., -0.5, -size_half),
-        )),
-    ));
-    cmd.spawn((
-        wall_x,
-        RigidBody::Fixed,
-        ColliderScale::Absolute(Vec3::ONE),
-        CollisionGroups::new(STATIC_GROUP, Group::ALL),
-        Friction::coefficient(0.5),
-        Restitution::coefficient(0.),
-        TransformBundle::from_transform(Transform::from_translation(
-            t0 + Vec3::new(0., -0.5, size_half),
-        )),
-    ));
-    let wall_z = Collider::cuboid(1., 10., size_half);
-    cmd.spawn((
-        wall_z.clone(),
-        RigidBody::Fixed,
-        ColliderScale::Absolute(Vec3::ONE),
-        CollisionGroups::new(STATIC_GROUP, Group::ALL),
-        Friction::coefficient(0.5),
-        Restitution::coefficient(0.),
-        TransformBundle::from_transform(Transform::from_translation(
-            t0 + Vec3::new(-size_half, -0.5, 0.),
-        )),
-    ));
-    cmd.spawn((
-        wall_z,
-        RigidBody::Fixed,
-        ColliderScale::Absolute(Vec3::ONE),
-        CollisionGroups::new(STATIC_GROUP, Group::ALL),
-        Friction::coefficient(0.5),
-        Restitution::coefficient(0.),
-        TransformBundle::from_transform(Transform::from_translation(
-            t0 + Vec3::new(size_half, -0.5, 0.),
-        )),
-    ));
-
-    #[cfg(feature = "graphics")]
-    {
-        use bevy::render::texture::{
-            ImageAddressMode, ImageLoaderSettings, ImageSampler, ImageSamplerDescriptor,
-        };
-        use std::f32::consts::PI;
-        // let color_handle = asset_server.load("asphalt/asphalt_color.png");
-        // let rough_handle = asset_server.load("asphalt/asphalt_03_rough_4k.exr");
-        // let depth_handle = asset_server.load("asphalt/asphalt_03_disp_4k.png");
-        let repeat = |settings: &mut ImageLoaderSettings| {
-            settings.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
-                address_mode_u: ImageAddressMode::Repeat,
-                address_mode_v: ImageAddressMode::Repeat,
-                address_mode_w: ImageAddressMode::Repeat,
-                ..Default::default()
-            });
-        };
-        let normal_handle =
-            asset_server.load_with_settings("asphalt/asphalt_03_nor_gl_4k.jpg", repeat);
-        let arm_handle = asset_server.load_with_settings("asphalt/asphalt_03_arm_4k.jpg", repeat);
-        let diff_handle = asset_server.load_with_settings("asphalt/asphalt_03_diff_4k.jpg", repeat);
-        let asphalt_material = materials.add(StandardMaterial {
-            base_color: Color::rgb(0.27, 0.25, 0.33),
-            normal_map_texture: Some(normal_handle),
-            // depth_map: Some(depth_handle),
-            metallic: 1.0,
-            perceptual_roughness: 1.0,
-            metallic_roughness_texture: Some(arm_handle),
-            diffuse_transmission_texture: Some(diff_handle),
-            ..default()
-        });
-
-        let x = 10.;
-        let uvs = vec![[x, 0.0], [0.0, 0.0], [0.0, x], [x, x]];
-        let mesh = Mesh::from(Rectangle::new(size, size))
-            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-        let rotation = Quat::from_rotation_x(-PI / 2.);
-        let transform = Transform::from_translation(t0).with_rotation(rotation);
-        cmd.spawn(PbrBundle {
-            mesh: meshes.add(mesh),
-            material: asphalt_material,
-            transform,
-            ..Default::default()
-        });
-        cmd.spawn(DirectionalLightBundle {
-            directional_light: DirectionalLight {
-                shadows_enabled: true,
-                ..default()
-            },
-            transform: Transform {
-                translation: Vec3::new(0.0, 2.0, 0.0),
-                rotation: Quat::from_rotation_x(-PI / 4.),
-                ..default()
-            },
-            ..default()
-        });
+    NetworkLimits::default().connection_config()
+}
+
+/// Applies `PhysicsSettings` whenever they change, on the client once `ServerInfo` arrives.
+pub fn physics_settings_system(settings: Res<PhysicsSettings>, mut c: ResMut<RapierContext>) {
+    if !settings.is_changed() {
+        return;
     }
+    c.integration_parameters.num_solver_iterations =
+        std::num::NonZeroUsize::new(settings.solver_iterations.max(1)).unwrap();
+    c.integration_parameters.num_internal_pgs_iterations = settings.internal_pgs_iterations;
+    c.integration_parameters.num_additional_friction_iterations =
+        settings.additional_friction_iterations;
+    c.integration_parameters.erp = settings.erp;
+    // c.integration_parameters.joint_erp = 0.95;
 }
+
+/// Levels must fit in a square of this size centered on the origin, see `quantize`.
+pub const LEVEL_SIZE: f32 = 1000.;
+/// And within half this far above and below it.
+pub const LEVEL_HEIGHT: f32 = 128.;
+/// Car origin height resting on the floor.
+pub const SPAWN_HEIGHT: f32 = 1.51;
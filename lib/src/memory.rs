@@ -0,0 +1,148 @@
+//! In-process transport between a server and a client app, used by the `host` launcher
+//! in place of netcode: renet packets are handed over through channels.
+use crate::profile::UserData;
+use bevy::prelude::*;
+use bevy_renet::{
+    renet::{ClientId, RenetClient, RenetServer},
+    RenetReceive, RenetSend,
+};
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Mutex,
+};
+
+/// Id of the local client, `random_client_id` never hands it out to netcode clients.
+pub const HOST_CLIENT_ID: u64 = 0;
+
+/// Server side of the local client's connection.
+#[derive(Resource)]
+pub struct MemoryServerTransport {
+    pub client_id: ClientId,
+    /// What netcode would carry in the connect token.
+    pub user_data: UserData,
+    to_client: Sender<Vec<u8>>,
+    from_client: Mutex<Receiver<Vec<u8>>>,
+    added: bool,
+}
+
+#[derive(Resource)]
+pub struct MemoryClientTransport {
+    pub client_id: ClientId,
+    to_server: Sender<Vec<u8>>,
+    from_server: Mutex<Receiver<Vec<u8>>>,
+}
+
+pub fn memory_transport_pair(
+    client_id: ClientId,
+    user_data: UserData,
+) -> (MemoryServerTransport, MemoryClientTransport) {
+    let (to_client, from_server) = channel();
+    let (to_server, from_client) = channel();
+    (
+        MemoryServerTransport {
+            client_id,
+            user_data,
+            to_client,
+            from_client: Mutex::new(from_client),
+            added: false,
+        },
+        MemoryClientTransport {
+            client_id,
+            to_server,
+            from_server: Mutex::new(from_server),
+        },
+    )
+}
+
+fn memory_server_receive_system(
+    mut server: ResMut<RenetServer>,
+    mut transport: ResMut<MemoryServerTransport>,
+) {
+    let client_id = transport.client_id;
+    if !transport.added {
+        server.add_connection(client_id);
+        transport.added = true;
+    }
+    for packet in transport.from_client.lock().unwrap().try_iter() {
+        // The client is gone once the server disconnected it
+        if server.process_packet_from(&packet, client_id).is_err() {
+            break;
+        }
+    }
+}
+
+fn memory_server_send_system(
+    mut server: ResMut<RenetServer>,
+    transport: Res<MemoryServerTransport>,
+) {
+    let Ok(packets) = server.get_packets_to_send(transport.client_id) else {
+        return;
+    };
+    for packet in packets {
+        // The client app exited, the server notices by timeout
+        if transport.to_client.send(packet).is_err() {
+            return;
+        }
+    }
+}
+
+fn memory_client_receive_system(
+    mut client: ResMut<RenetClient>,
+    transport: Res<MemoryClientTransport>,
+) {
+    if client.is_connecting() {
+        client.set_connected();
+    }
+    for packet in transport.from_server.lock().unwrap().try_iter() {
+        client.process_packet(&packet);
+    }
+}
+
+fn memory_client_send_system(
+    mut client: ResMut<RenetClient>,
+    transport: Res<MemoryClientTransport>,
+) {
+    for packet in client.get_packets_to_send() {
+        if transport.to_server.send(packet).is_err() {
+            return;
+        }
+    }
+}
+
+pub struct MemoryServerPlugin;
+
+impl Plugin for MemoryServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            memory_server_receive_system
+                .in_set(RenetReceive)
+                .run_if(resource_exists::<MemoryServerTransport>),
+        );
+        app.add_systems(
+            PostUpdate,
+            memory_server_send_system
+                .in_set(RenetSend)
+                .run_if(resource_exists::<MemoryServerTransport>),
+        );
+    }
+}
+
+pub struct MemoryClientPlugin;
+
+impl Plugin for MemoryClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            memory_client_receive_system
+                .in_set(RenetReceive)
+                .run_if(resource_exists::<MemoryClientTransport>),
+        );
+        app.add_systems(
+            PostUpdate,
+            memory_client_send_system
+                .in_set(RenetSend)
+                .run_if(resource_exists::<MemoryClientTransport>),
+        );
+    }
+}
@@ -0,0 +1,56 @@
+//! Player name, color and car, carried in the netcode `user_data` of the connection.
+use bevy_renet::renet::transport::NETCODE_USER_DATA_BYTES;
+use bincode::Options;
+
+use crate::{protocol::ProtocolError, CarModel, PlayerProfile};
+
+pub const MAX_NAME_CHARS: usize = 24;
+
+pub type UserData = [u8; NETCODE_USER_DATA_BYTES];
+
+impl Default for PlayerProfile {
+    fn default() -> Self {
+        Self {
+            name: "Player".to_string(),
+            color: [230, 60, 40],
+            car: CarModel::default(),
+        }
+    }
+}
+
+impl CarModel {
+    pub const ALL: [CarModel; 1] = [CarModel::Race];
+}
+
+impl PlayerProfile {
+    /// Bincode padded with zeros, a sanitized profile always fits.
+    pub fn to_user_data(&self) -> UserData {
+        let mut user_data = [0; NETCODE_USER_DATA_BYTES];
+        let bytes = bincode::serialize(&self.clone().sanitized()).unwrap();
+        user_data[..bytes.len()].copy_from_slice(&bytes);
+        user_data
+    }
+
+    /// Rejects what doesn't decode, e.g. an unknown car, and sanitizes the rest.
+    pub fn from_user_data(user_data: &UserData) -> Result<Self, ProtocolError> {
+        let profile: Self = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(NETCODE_USER_DATA_BYTES as u64)
+            .allow_trailing_bytes()
+            .deserialize(user_data)
+            .map_err(ProtocolError::Malformed)?;
+        Ok(profile.sanitized())
+    }
+
+    /// Players can type anything: keeps at most `MAX_NAME_CHARS` printable chars, never empty.
+    pub fn sanitized(mut self) -> Self {
+        let name: String = self.name.chars().filter(|c| !c.is_control()).collect();
+        let name: String = name.trim().chars().take(MAX_NAME_CHARS).collect();
+        self.name = if name.is_empty() {
+            Self::default().name
+        } else {
+            name
+        };
+        self
+    }
+}
@@ -0,0 +1,55 @@
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+/// Upper bound for anything a client sends: inputs and commands are tiny.
+pub const MAX_CLIENT_MESSAGE_BYTES: u64 = 1024;
+/// Upper bound for server messages, a full snapshot of 64 cars fits well below it.
+pub const MAX_SERVER_MESSAGE_BYTES: u64 = 64 * 1024;
+/// Malformed messages a client may send before the server disconnects it.
+pub const MAX_PROTOCOL_VIOLATIONS: u32 = 3;
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    TooLarge { size: usize, limit: u64 },
+    Malformed(bincode::Error),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::TooLarge { size, limit } => {
+                write!(f, "message of {} bytes exceeds limit of {}", size, limit)
+            }
+            ProtocolError::Malformed(e) => write!(f, "malformed message: {}", e),
+            ProtocolError::Invalid(reason) => write!(f, "invalid message: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Same encoding as `bincode::serialize`, but bounded by `limit` so a bogus
+/// length prefix can't make us allocate, and trailing garbage is rejected.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8], limit: u64) -> Result<T, ProtocolError> {
+    if bytes.len() as u64 > limit {
+        return Err(ProtocolError::TooLarge {
+            size: bytes.len(),
+            limit,
+        });
+    }
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(limit)
+        .deserialize(bytes)
+        .map_err(ProtocolError::Malformed)
+}
+
+pub fn decode_client_message<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ProtocolError> {
+    decode(bytes, MAX_CLIENT_MESSAGE_BYTES)
+}
+
+pub fn decode_server_message<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ProtocolError> {
+    decode(bytes, MAX_SERVER_MESSAGE_BYTES)
+}
@@ -0,0 +1,214 @@
+//! Compact wire encoding of car and wheel transforms used by `EntityState`,
+//! and of the analog controls in `PlayerInput`.
+use bevy::prelude::*;
+use std::f32::consts::{FRAC_1_SQRT_2, TAU};
+
+use crate::{EntityState, PlayerInput, WheelState};
+
+/// Horizontal positions cover `[-512, 512)` m, enough for any level within `LEVEL_SIZE`.
+const XZ_RANGE: f32 = 512.;
+/// Cars rarely leave the ground by much, `[-64, 64)` m, the same as `LEVEL_HEIGHT`.
+const Y_RANGE: f32 = 64.;
+const XZ_BITS: u32 = 21;
+const Y_BITS: u32 = 18;
+/// Steps per meter, the same for all axes: 2^21 steps over 1024 m.
+const POSITION_SCALE: f32 = (1 << XZ_BITS) as f32 / (2. * XZ_RANGE);
+
+const ROTATION_BITS: u32 = 10;
+const ROTATION_MAX: f32 = ((1 << ROTATION_BITS) - 1) as f32;
+
+/// Suspension travel in 0.1 mm steps, about ±3.2 m.
+const SUSPENSION_SCALE: f32 = 10000.;
+
+fn quantize_axis(v: f32, range: f32, bits: u32) -> u64 {
+    let max = ((1u64 << bits) - 1) as f32;
+    ((v + range) * POSITION_SCALE).round().clamp(0., max) as u64
+}
+
+fn dequantize_axis(q: u64, range: f32, bits: u32) -> f32 {
+    (q & ((1 << bits) - 1)) as f32 / POSITION_SCALE - range
+}
+
+/// Fixed-point position packed as x:21 | z:21 | y:18 bits, clamped to the arena bounds.
+pub fn quantize_position(v: Vec3) -> u64 {
+    quantize_axis(v.x, XZ_RANGE, XZ_BITS)
+        | quantize_axis(v.z, XZ_RANGE, XZ_BITS) << XZ_BITS
+        | quantize_axis(v.y, Y_RANGE, Y_BITS) << (2 * XZ_BITS)
+}
+
+pub fn dequantize_position(q: u64) -> Vec3 {
+    Vec3::new(
+        dequantize_axis(q, XZ_RANGE, XZ_BITS),
+        dequantize_axis(q >> (2 * XZ_BITS), Y_RANGE, Y_BITS),
+        dequantize_axis(q >> XZ_BITS, XZ_RANGE, XZ_BITS),
+    )
+}
+
+/// Smallest-three: index of the largest component in 2 bits, the other three
+/// in 10 bits each. The largest is rebuilt from unit length, its sign made positive.
+pub fn quantize_rotation(q: Quat) -> u32 {
+    let a = q.normalize().to_array();
+    let largest = (0..4)
+        .max_by(|&i, &j| a[i].abs().total_cmp(&a[j].abs()))
+        .unwrap();
+    let sign = if a[largest] < 0. { -1. } else { 1. };
+    let mut packed = largest as u32;
+    let mut shift = 2;
+    for (i, v) in a.iter().enumerate() {
+        if i == largest {
+            continue;
+        }
+        let normalized = (v * sign / FRAC_1_SQRT_2 + 1.) / 2.;
+        let n = (normalized * ROTATION_MAX).round().clamp(0., ROTATION_MAX) as u32;
+        packed |= n << shift;
+        shift += ROTATION_BITS;
+    }
+    packed
+}
+
+pub fn dequantize_rotation(packed: u32) -> Quat {
+    let largest = (packed & 0b11) as usize;
+    let mut a = [0f32; 4];
+    let mut shift = 2;
+    let mut sum = 0.;
+    for (i, v) in a.iter_mut().enumerate() {
+        if i == largest {
+            continue;
+        }
+        let n = (packed >> shift) & ((1 << ROTATION_BITS) - 1);
+        *v = (n as f32 / ROTATION_MAX * 2. - 1.) * FRAC_1_SQRT_2;
+        sum += *v * *v;
+        shift += ROTATION_BITS;
+    }
+    a[largest] = (1. - sum).max(0.).sqrt();
+    Quat::from_array(a).normalize()
+}
+
+fn quantize_angle(angle: f32) -> u16 {
+    ((angle.rem_euclid(TAU) / TAU * 65536.).round() as u32 & 0xffff) as u16
+}
+
+fn dequantize_angle(q: u16) -> f32 {
+    q as f32 / 65536. * TAU
+}
+
+/// Wheel transforms relative to the car at spawn, the rest pose `WheelState` is relative to.
+/// Captured on both server and client since both spawn cars with `spawn_car`.
+#[derive(Debug, Clone, Copy, Component)]
+pub struct WheelAnchors(pub [(Vec3, Quat); 4]);
+
+impl WheelAnchors {
+    pub fn new(car: &Transform, wheels: [&Transform; 4]) -> Self {
+        let inverse = car.rotation.inverse();
+        Self(wheels.map(|wheel| {
+            (
+                inverse * (wheel.translation - car.translation),
+                inverse * wheel.rotation,
+            )
+        }))
+    }
+}
+
+impl WheelState {
+    /// Keeps only the offset along the car's up axis from the anchor,
+    /// rotation relative to the anchor as steer (Y), camber (Z) and spin (X) angles.
+    pub fn new(car: &Transform, wheel: &Transform, anchor: &(Vec3, Quat)) -> Self {
+        let inverse = car.rotation.inverse();
+        let local_translation = inverse * (wheel.translation - car.translation);
+        let relative_rotation = inverse * wheel.rotation * anchor.1.inverse();
+        let (steer, camber, spin) = relative_rotation.to_euler(EulerRot::YZX);
+        let suspension = ((local_translation.y - anchor.0.y) * SUSPENSION_SCALE)
+            .round()
+            .clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        Self {
+            suspension,
+            steer: quantize_angle(steer),
+            camber: quantize_angle(camber),
+            spin: quantize_angle(spin),
+        }
+    }
+
+    pub fn transform(&self, car: &Transform, anchor: &(Vec3, Quat)) -> Transform {
+        let local_translation = anchor.0 + Vec3::Y * (self.suspension as f32 / SUSPENSION_SCALE);
+        let relative_rotation = Quat::from_euler(
+            EulerRot::YZX,
+            dequantize_angle(self.steer),
+            dequantize_angle(self.camber),
+            dequantize_angle(self.spin),
+        );
+        Transform {
+            translation: car.translation + car.rotation * local_translation,
+            rotation: car.rotation * relative_rotation * anchor.1,
+            ..default()
+        }
+    }
+}
+
+impl EntityState {
+    pub fn new(car: &Transform, wheels: [&Transform; 4], anchors: &WheelAnchors) -> Self {
+        // Wheels are encoded relative to the car as the client will decode it
+        let decoded_car = Transform {
+            translation: dequantize_position(quantize_position(car.translation)),
+            rotation: dequantize_rotation(quantize_rotation(car.rotation)),
+            ..default()
+        };
+        Self {
+            translation: quantize_position(car.translation),
+            rotation: quantize_rotation(car.rotation),
+            wheels: std::array::from_fn(|i| {
+                WheelState::new(&decoded_car, wheels[i], &anchors.0[i])
+            }),
+        }
+    }
+
+    pub fn car_transform(&self) -> Transform {
+        Transform {
+            translation: dequantize_position(self.translation),
+            rotation: dequantize_rotation(self.rotation),
+            ..default()
+        }
+    }
+
+    pub fn wheel_transforms(&self, anchors: &WheelAnchors) -> [Transform; 4] {
+        let car = self.car_transform();
+        std::array::from_fn(|i| self.wheels[i].transform(&car, &anchors.0[i]))
+    }
+}
+
+impl PlayerInput {
+    /// Pedals in `0..=1` and steering in `-1..=1` (positive is right), clamped.
+    pub fn set_controls(&mut self, throttle: f32, brake: f32, steering: f32, handbrake: bool) {
+        self.throttle = (throttle.clamp(0., 1.) * u8::MAX as f32).round() as u8;
+        self.brake = (brake.clamp(0., 1.) * u8::MAX as f32).round() as u8;
+        self.steering = (steering.clamp(-1., 1.) * i8::MAX as f32).round() as i8;
+        self.handbrake = handbrake;
+    }
+
+    pub fn throttle(&self) -> f32 {
+        self.throttle as f32 / u8::MAX as f32
+    }
+
+    pub fn brake(&self) -> f32 {
+        self.brake as f32 / u8::MAX as f32
+    }
+
+    /// `i8::MIN` can only come from a misbehaving client, it is clamped like the rest.
+    pub fn steering(&self) -> f32 {
+        (self.steering as f32 / i8::MAX as f32).clamp(-1., 1.)
+    }
+}
+
+/// Captures `WheelAnchors` for newly spawned cars, run after the spawn commands are applied.
+pub fn wheel_anchors_system(
+    mut cmd: Commands,
+    cars: Query<(Entity, &Transform, &bevy_garage_car::CarWheels), Without<WheelAnchors>>,
+    wheels: Query<&Transform, With<bevy_garage_car::Wheel>>,
+) {
+    for (entity, transform, car_wheels) in cars.iter() {
+        let Ok(wheel_transforms) = wheels.get_many(car_wheels.entities) else {
+            continue;
+        };
+        cmd.entity(entity)
+            .insert(WheelAnchors::new(transform, wheel_transforms));
+    }
+}
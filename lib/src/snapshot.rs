@@ -0,0 +1,83 @@
+use bevy::prelude::*;
+use std::collections::{HashMap, VecDeque};
+
+use crate::{EntityState, NetworkedEntities, ProtocolError};
+
+/// Full state of all networked entities at one tick, what a delta snapshot decodes to.
+pub type WorldState = HashMap<Entity, EntityState>;
+
+/// Recent world states by tick, the baselines deltas are encoded against.
+/// The server keeps the states it sent, the client the states it decoded.
+#[derive(Debug, Default, Resource)]
+pub struct SnapshotHistory {
+    states: VecDeque<(u32, WorldState)>,
+}
+
+impl SnapshotHistory {
+    /// About a second of ticks, a client acking anything older gets a full snapshot.
+    pub const LEN: usize = 64;
+
+    pub fn insert(&mut self, tick: u32, state: WorldState) {
+        if self.states.len() == Self::LEN {
+            self.states.pop_front();
+        }
+        self.states.push_back((tick, state));
+    }
+
+    pub fn get(&self, tick: u32) -> Option<&WorldState> {
+        self.states
+            .iter()
+            .find(|(t, _)| *t == tick)
+            .map(|(_, state)| state)
+    }
+}
+
+impl NetworkedEntities {
+    /// Encodes `current` against `baseline` when given, otherwise as a full snapshot.
+    /// Entities whose state is unchanged since the baseline are left out.
+    pub fn encode(
+        tick: u32,
+        ack_input: u32,
+        current: &WorldState,
+        baseline: Option<(u32, &WorldState)>,
+    ) -> Self {
+        let mut snapshot = NetworkedEntities {
+            tick,
+            baseline_tick: baseline.map(|(baseline_tick, _)| baseline_tick),
+            ack_input,
+            ..default()
+        };
+        for (entity, state) in current.iter() {
+            let unchanged =
+                baseline.is_some_and(|(_, baseline)| baseline.get(entity) == Some(state));
+            if !unchanged {
+                snapshot.entities.push(*entity);
+                snapshot.states.push(*state);
+            }
+        }
+        if let Some((_, baseline)) = baseline {
+            snapshot.removed = baseline
+                .keys()
+                .filter(|entity| !current.contains_key(entity))
+                .copied()
+                .collect();
+        }
+        snapshot
+    }
+
+    /// Rebuilds the full world state, `baseline` must be the state at `baseline_tick`.
+    pub fn decode(&self, baseline: Option<&WorldState>) -> Result<WorldState, ProtocolError> {
+        let mut state = match (self.baseline_tick, baseline) {
+            (None, _) => WorldState::default(),
+            (Some(_), Some(baseline)) => baseline.clone(),
+            (Some(_), None) => return Err(ProtocolError::Invalid("delta baseline missing")),
+        };
+        for entity in self.removed.iter() {
+            state.remove(entity);
+        }
+        for (entity, entity_state) in self.entities.iter().zip(self.states.iter()) {
+            state.insert(*entity, *entity_state);
+        }
+        Ok(state)
+    }
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+/// Defines wire types and records their source text in `PROTOCOL_SCHEMA`,
+/// so any change to a field or variant changes `PROTOCOL_SCHEMA_HASH`.
+macro_rules! protocol_schema {
+    ($($item:item)*) => {
+        $($item)*
+        pub const PROTOCOL_SCHEMA: &str = concat!($(stringify!($item)),*);
+        pub const PROTOCOL_SCHEMA_HASH: u64 = $crate::version::fnv1a(PROTOCOL_SCHEMA.as_bytes());
+    };
+}
+
+/// FNV-1a, stable across builds and platforms unlike `DefaultHasher`.
+pub const fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+    hash
+}
+
+/// First message the server sends to every client on `ServerChannel::ServerMessages`.
+/// Keep this struct unchanged so older and newer builds can still read it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+    pub crate_version: String,
+    pub schema_hash: u64,
+    /// See `Level::hash`, a client with another level switches to the one the server sends.
+    pub level_hash: u64,
+}
+
+impl ProtocolVersion {
+    pub fn current(level: &crate::Level) -> Self {
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            schema_hash: crate::PROTOCOL_SCHEMA_HASH,
+            level_hash: level.hash(),
+        }
+    }
+
+    /// Describes why `server` can't talk to this build, if it can't.
+    /// A different level isn't a reason, see `level_hash`.
+    pub fn check_compatible(&self, server: &ProtocolVersion) -> Result<(), String> {
+        if self.schema_hash != server.schema_hash {
+            return Err(format!(
+                "protocol schema mismatch: client {} ({:016x}), server {} ({:016x})",
+                self.crate_version, self.schema_hash, server.crate_version, server.schema_hash
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use bevy_garage_arena_lib::{level::DEFAULT_LEVEL, Level};
+
+#[test]
+fn default_level_loads() {
+    let level = Level::load(DEFAULT_LEVEL).unwrap();
+    assert_eq!(level.colliders.iter().filter(|c| c.wall).count(), 4);
+    assert_eq!(level.spawn_points.len(), 20);
+    assert!(Level::load("no_such_level").is_err());
+    assert_eq!(Level::builtin().hash(), level.hash());
+}
+
+#[test]
+fn broken_level_is_rejected() {
+    let level = r#"(
+        materials: [(name: "ground")],
+        colliders: [
+            (shape: Ball(radius: 0.0), translation: (0.0, 0.0, 0.0), material: "ice"),
+            (shape: Cuboid(half_extents: (1.0, 1.0, 1.0)), translation: (900.0, 0.0, 0.0), material: "ground"),
+        ],
+        spawn_points: [],
+    )"#;
+    let error = Level::from_ron(level).unwrap_err();
+    for problem in [
+        "unknown material 'ice'",
+        "collider 0 needs a size",
+        "collider 1 is outside",
+        "no spawn points",
+    ] {
+        assert!(error.contains(problem), "{}", error);
+    }
+
+    let mut huge = Level::load(DEFAULT_LEVEL).unwrap();
+    let collider = huge.colliders[0].clone();
+    huge.colliders.resize(2000, collider);
+    let error = huge.validate().unwrap_err();
+    assert!(
+        error.contains("exceeds the 65536 byte message limit"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn level_height_is_bounded() {
+    let mut level = Level::load(DEFAULT_LEVEL).unwrap();
+    level.colliders[0].translation[1] = -100.;
+    level.spawn_points[0].translation[1] = 65.;
+    let error = level.validate().unwrap_err();
+    assert!(error.contains("collider 0 is outside"), "{}", error);
+    assert!(error.contains("spawn point 0 is outside"), "{}", error);
+}
+
+#[test]
+fn rotations_must_be_finite() {
+    let mut level = Level::load(DEFAULT_LEVEL).unwrap();
+    level.colliders[1].rotation[0] = f32::NAN;
+    level.spawn_points[2].yaw = f32::INFINITY;
+    let error = level.validate().unwrap_err();
+    assert!(
+        error.contains("collider 1 needs a finite rotation"),
+        "{}",
+        error
+    );
+    assert!(
+        error.contains("spawn point 2 needs a finite yaw"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn level_hash_covers_physics_only() {
+    let level = Level::load(DEFAULT_LEVEL).unwrap();
+    let hash = level.hash();
+    assert_eq!(Level::from_ron(&level.to_ron()).unwrap().hash(), hash);
+
+    let mut looks = level.clone();
+    looks.materials[0].color = [0., 1., 0.];
+    looks.lights.clear();
+    looks.spawn_points.truncate(1);
+    assert_eq!(looks.hash(), hash);
+
+    let mut friction = level.clone();
+    friction.materials[0].friction += 0.1;
+    assert_ne!(friction.hash(), hash);
+
+    let mut moved = level.clone();
+    moved.colliders[1].translation[2] -= 1.;
+    assert_ne!(moved.hash(), hash);
+}
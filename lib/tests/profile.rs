@@ -0,0 +1,33 @@
+use bevy_garage_arena_lib::{profile::MAX_NAME_CHARS, CarModel, PlayerProfile};
+
+#[test]
+fn profile_round_trips_through_user_data() {
+    let profile = PlayerProfile {
+        name: "Driver".to_string(),
+        color: [10, 200, 30],
+        car: CarModel::Race,
+    };
+    let decoded = PlayerProfile::from_user_data(&profile.to_user_data()).unwrap();
+    assert_eq!(decoded, profile);
+}
+
+#[test]
+fn profile_name_is_sanitized() {
+    let profile = PlayerProfile {
+        name: format!("  \u{7}{}  ", "é".repeat(100)),
+        ..Default::default()
+    };
+    let decoded = PlayerProfile::from_user_data(&profile.to_user_data()).unwrap();
+    assert_eq!(decoded.name, "é".repeat(MAX_NAME_CHARS));
+
+    let blank = PlayerProfile {
+        name: " \n ".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(blank.sanitized().name, PlayerProfile::default().name);
+}
+
+#[test]
+fn garbage_user_data_is_rejected() {
+    assert!(PlayerProfile::from_user_data(&[0xff; 256]).is_err());
+}
@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+use bevy_garage_arena_lib::{
+    quantize::{
+        dequantize_position, dequantize_rotation, quantize_position, quantize_rotation,
+        WheelAnchors,
+    },
+    EntityState, PlayerInput, LEVEL_SIZE,
+};
+
+fn random_rotation() -> Quat {
+    Quat::from_xyzw(
+        fastrand::f32() - 0.5,
+        fastrand::f32() - 0.5,
+        fastrand::f32() - 0.5,
+        fastrand::f32() - 0.5,
+    )
+    .normalize()
+}
+
+fn random_position() -> Vec3 {
+    Vec3::new(
+        (fastrand::f32() - 0.5) * LEVEL_SIZE,
+        fastrand::f32() * 20.,
+        (fastrand::f32() - 0.5) * LEVEL_SIZE,
+    )
+}
+
+#[test]
+fn position_round_trip() {
+    fastrand::seed(1);
+    for _ in 0..10_000 {
+        let v = random_position();
+        let error = dequantize_position(quantize_position(v)).distance(v);
+        assert!(error < 0.001, "{} off by {}", v, error);
+    }
+}
+
+#[test]
+fn position_clamped_to_arena() {
+    let v = dequantize_position(quantize_position(Vec3::new(10_000., -1000., -10_000.)));
+    assert!(v.x < 513. && v.y > -65. && v.z > -513., "{}", v);
+}
+
+#[test]
+fn rotation_round_trip() {
+    fastrand::seed(2);
+    for _ in 0..10_000 {
+        let q = random_rotation();
+        let error = dequantize_rotation(quantize_rotation(q)).angle_between(q);
+        assert!(error < 0.005, "{} off by {} rad", q, error);
+    }
+}
+
+#[test]
+fn wheels_round_trip() {
+    fastrand::seed(3);
+    let spawn = Transform::default();
+    let rest: [Transform; 4] = [
+        Vec3::new(-0.8, -0.2, -1.3),
+        Vec3::new(0.8, -0.2, -1.3),
+        Vec3::new(-0.8, -0.2, 1.3),
+        Vec3::new(0.8, -0.2, 1.3),
+    ]
+    .map(Transform::from_translation);
+    let anchors = WheelAnchors::new(&spawn, rest.each_ref());
+
+    for _ in 0..1_000 {
+        let car = Transform {
+            translation: random_position(),
+            rotation: random_rotation(),
+            ..default()
+        };
+        let suspension = (fastrand::f32() - 0.5) * 0.4;
+        let local = Quat::from_rotation_y((fastrand::f32() - 0.5) * 1.2)
+            * Quat::from_rotation_x(fastrand::f32() * 100.);
+        let wheels = rest.map(|wheel| Transform {
+            translation: car.translation
+                + car.rotation * (wheel.translation + Vec3::Y * suspension),
+            rotation: car.rotation * local,
+            ..default()
+        });
+
+        let state = EntityState::new(&car, wheels.each_ref(), &anchors);
+        let decoded = state.wheel_transforms(&anchors);
+        for (wheel, decoded) in wheels.iter().zip(decoded.iter()) {
+            let error = decoded.translation.distance(wheel.translation);
+            assert!(error < 0.01, "wheel off by {} m", error);
+            let error = decoded.rotation.angle_between(wheel.rotation);
+            assert!(error < 0.01, "wheel off by {} rad", error);
+        }
+    }
+}
+
+#[test]
+fn controls_round_trip_and_clamp() {
+    let mut input = PlayerInput::default();
+    input.set_controls(0.5, 2., -0.3, true);
+    assert!((input.throttle() - 0.5).abs() < 0.005);
+    assert_eq!(input.brake(), 1.);
+    assert!((input.steering() + 0.3).abs() < 0.005);
+    assert!(input.handbrake);
+
+    input.steering = i8::MIN;
+    assert_eq!(input.steering(), -1.);
+}
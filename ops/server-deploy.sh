@@ -6,6 +6,7 @@ project="bevy_garage_arena_server";
 cargo build --release -p $project --target $TARGET --no-default-features --features=headless
 
 rsync -v --progress target/$TARGET/release/$project $SERVER_HOST:$SERVER_DIR/
+# on the host: $project --bind 0.0.0.0:5000 --public-addr <public ip>:5000
 
 # rsync -arvC --progress --copy-links \
 #     assets/ \
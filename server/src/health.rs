@@ -0,0 +1,150 @@
+//! Car health: hard hits against walls and projectile hits take it away,
+//! a car with none left is destroyed and respawns after a delay.
+use bevy::prelude::*;
+use bevy_garage_arena_lib::{Player, ServerChannel, ServerMessages, ServerTick, Wall};
+use bevy_garage_car::{Car, CarWheels};
+use bevy_rapier3d::prelude::*;
+use bevy_renet::renet::RenetServer;
+use std::collections::HashMap;
+
+use crate::{
+    car_of,
+    projectile::{projectile_hit_system, ProjectileHit},
+    reached,
+    respawn::{RespawnReason, RespawnRequest},
+    spawn::SpawnProtection,
+    ServerSettings,
+};
+
+#[derive(Debug, Component)]
+pub struct Health {
+    pub current: f32,
+}
+
+/// A car out of health, it can't be driven until it respawns at `respawn_tick`.
+#[derive(Debug, Component)]
+pub struct Destroyed {
+    respawn_tick: u32,
+}
+
+/// Walls report contacts strong enough to do damage.
+fn wall_contact_events_system(
+    mut cmd: Commands,
+    settings: Res<ServerSettings>,
+    walls: Query<Entity, Added<Wall>>,
+) {
+    for wall in walls.iter() {
+        cmd.entity(wall).insert((
+            ActiveEvents::CONTACT_FORCE_EVENTS,
+            ContactForceEventThreshold(settings.damage.min_wall_force()),
+        ));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn damage_system(
+    mut cmd: Commands,
+    mut contacts: EventReader<ContactForceEvent>,
+    mut hits: EventReader<ProjectileHit>,
+    mut server: ResMut<RenetServer>,
+    tick: Res<ServerTick>,
+    settings: Res<ServerSettings>,
+    walls: Query<(), With<Wall>>,
+    cars: Query<(Entity, &CarWheels), With<Player>>,
+    parents: Query<&Parent>,
+    players: Query<&Player>,
+    mut healths: Query<(&Player, &mut Health), (Without<Destroyed>, Without<SpawnProtection>)>,
+) {
+    let mut damage: HashMap<Entity, f32> = HashMap::new();
+    let mut attackers: HashMap<Entity, Entity> = HashMap::new();
+    for contact in contacts.read() {
+        let other = if walls.contains(contact.collider1) {
+            contact.collider2
+        } else if walls.contains(contact.collider2) {
+            contact.collider1
+        } else {
+            continue;
+        };
+        let Some(car) = car_of(other, &cars, &parents) else {
+            continue;
+        };
+        // Body and wheels hitting together are one hit
+        let wall_damage = settings.damage.wall_damage(contact.total_force_magnitude);
+        let car_damage = damage.entry(car).or_default();
+        *car_damage = car_damage.max(wall_damage);
+    }
+    for hit in hits.read() {
+        *damage.entry(hit.target).or_default() += settings.damage.projectile;
+        attackers.insert(hit.target, hit.owner);
+    }
+
+    for (car, amount) in damage {
+        let Ok((player, mut health)) = healths.get_mut(car) else {
+            continue;
+        };
+        if amount <= 0. {
+            continue;
+        }
+        health.current = (health.current - amount).max(0.);
+        let message = bincode::serialize(&ServerMessages::PlayerHealth {
+            id: player.id,
+            health: health.current,
+        })
+        .unwrap();
+        server.broadcast_message(ServerChannel::ServerMessages, message);
+        if health.current > 0. {
+            continue;
+        }
+        let by = attackers
+            .get(&car)
+            .and_then(|attacker| players.get(*attacker).ok())
+            .map(|attacker| attacker.id);
+        println!("Player {} destroyed by {:?}.", player.id, by);
+        cmd.entity(car).insert(Destroyed {
+            respawn_tick: tick
+                .0
+                .wrapping_add(settings.ticks(settings.damage.respawn_delay)),
+        });
+        let message =
+            bincode::serialize(&ServerMessages::PlayerDestroyed { id: player.id, by }).unwrap();
+        server.broadcast_message(ServerChannel::ServerMessages, message);
+    }
+}
+
+/// Keeps wrecks still, then respawns them.
+fn destroyed_system(
+    mut cmd: Commands,
+    mut respawns: EventWriter<RespawnRequest>,
+    tick: Res<ServerTick>,
+    mut cars: Query<(Entity, &Destroyed, &mut Car)>,
+) {
+    for (entity, destroyed, mut car) in cars.iter_mut() {
+        car.gas = 0.;
+        car.brake = 1.;
+        car.steering = 0.;
+        if reached(tick.0, destroyed.respawn_tick) {
+            cmd.entity(entity).remove::<Destroyed>();
+            respawns.send(RespawnRequest {
+                car: entity,
+                reason: RespawnReason::Destroyed,
+            });
+        }
+    }
+}
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, wall_contact_events_system);
+        app.add_systems(
+            FixedUpdate,
+            (
+                destroyed_system.before(PhysicsSet::SyncBackend),
+                damage_system
+                    .after(PhysicsSet::Writeback)
+                    .after(projectile_hit_system),
+            ),
+        );
+    }
+}
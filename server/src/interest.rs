@@ -0,0 +1,125 @@
+use bevy::prelude::*;
+use bevy_garage_arena_lib::{
+    protocol::MAX_SERVER_MESSAGE_BYTES,
+    snapshot::{SnapshotHistory, WorldState},
+    EntityState, NetworkLimits,
+};
+use bevy_renet::renet::ClientId;
+use std::collections::{HashMap, VecDeque};
+
+/// Rough size of the `NetworkedEntities` fields outside the per-entity vectors.
+const SNAPSHOT_HEADER_BYTES: usize = 64;
+
+/// Ticks between updates of a car at `distance` m from the viewer.
+fn send_interval(distance: f32) -> u32 {
+    match distance {
+        d if d < 100. => 1,
+        d if d < 300. => 3,
+        _ => 10,
+    }
+}
+
+/// Bytes a single client's snapshot may take per tick.
+pub fn snapshot_budget(limits: &NetworkLimits) -> usize {
+    let available = limits.available_bytes_per_tick as usize;
+    limits
+        .snapshot_bytes
+        .min(available / 2)
+        .min(MAX_SERVER_MESSAGE_BYTES as usize)
+}
+
+/// What one client was sent: the world as it decoded it per tick,
+/// and per tick which tick each car's state in that snapshot is from.
+#[derive(Debug, Default)]
+pub struct ClientView {
+    pub history: SnapshotHistory,
+    stamps: VecDeque<(u32, HashMap<Entity, u32>)>,
+}
+
+impl ClientView {
+    fn stamps(&self, tick: u32) -> Option<&HashMap<Entity, u32>> {
+        self.stamps
+            .iter()
+            .find(|(t, _)| *t == tick)
+            .map(|(_, stamps)| stamps)
+    }
+
+    /// Picks which of the changed `candidates` go into this tick's snapshot: the viewer's own car,
+    /// then cars due for an update by distance from it, most overdue first, within `budget` bytes.
+    /// The others are listed as held back, so their ids count against the budget too.
+    /// Cars are overdue from the state the client acked with `baseline_tick`,
+    /// so one lost in a dropped snapshot isn't held back.
+    pub fn select(
+        &self,
+        tick: u32,
+        baseline_tick: Option<u32>,
+        viewer: Option<(Entity, Vec3)>,
+        candidates: Vec<(Entity, EntityState, Vec3)>,
+        budget: usize,
+    ) -> Vec<(Entity, EntityState)> {
+        let acked = baseline_tick.and_then(|baseline_tick| self.stamps(baseline_tick));
+        let mut bytes = SNAPSHOT_HEADER_BYTES
+            + candidates
+                .iter()
+                .map(|(entity, _, _)| bincode::serialized_size(entity).unwrap() as usize)
+                .sum::<usize>();
+        let mut due: Vec<(f32, f32, Entity, EntityState)> = candidates
+            .into_iter()
+            .filter_map(|(entity, state, position)| {
+                if viewer.is_some_and(|(own, _)| own == entity) {
+                    return Some((f32::INFINITY, 0., entity, state));
+                }
+                let distance = viewer.map_or(0., |(_, viewer)| viewer.distance(position));
+                let priority = match acked.and_then(|acked| acked.get(&entity)) {
+                    Some(stamp) => {
+                        tick.wrapping_sub(*stamp) as f32 / send_interval(distance) as f32
+                    }
+                    None => f32::MAX,
+                };
+                (priority >= 1.).then_some((priority, distance, entity, state))
+            })
+            .collect();
+        due.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.total_cmp(&b.1)));
+
+        let mut selected = vec![];
+        for (_, _, entity, state) in due {
+            bytes += bincode::serialized_size(&state).unwrap() as usize;
+            if bytes > budget {
+                break;
+            }
+            selected.push((entity, state));
+        }
+        selected
+    }
+
+    /// Records the snapshot sent at `tick` against `baseline_tick`, `updated` are the cars in it.
+    pub fn insert(
+        &mut self,
+        tick: u32,
+        state: WorldState,
+        baseline_tick: Option<u32>,
+        updated: &[Entity],
+    ) {
+        let mut stamps: HashMap<Entity, u32> = baseline_tick
+            .and_then(|baseline_tick| self.stamps(baseline_tick))
+            .map(|acked| {
+                acked
+                    .iter()
+                    .filter(|(entity, _)| state.contains_key(entity))
+                    .map(|(entity, stamp)| (*entity, *stamp))
+                    .collect()
+            })
+            .unwrap_or_default();
+        for entity in updated {
+            stamps.insert(*entity, tick);
+        }
+        if self.stamps.len() == SnapshotHistory::LEN {
+            self.stamps.pop_front();
+        }
+        self.stamps.push_back((tick, stamps));
+        self.history.insert(tick, state);
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct ClientViews(pub HashMap<ClientId, ClientView>);
@@ -0,0 +1,714 @@
+use bevy::{
+    diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
+    prelude::*,
+};
+use bevy_garage_arena_lib::{
+    apply_player_input,
+    auth::{private_key_from_env, serve_tokens, unsecure_from_env, ConnectedClients, TokenIssuer},
+    memory::{MemoryServerPlugin, MemoryServerTransport},
+    physics_settings_system,
+    protocol::{decode_client_message, MAX_PROTOCOL_VIOLATIONS},
+    quantize::{wheel_anchors_system, WheelAnchors},
+    setup_level,
+    snapshot::WorldState,
+    ClientChannel, EntityState, Level, NetworkedEntities, Player, PlayerCommand, PlayerInput,
+    PlayerProfile, ProtocolVersion, ServerChannel, ServerMessages, ServerTick, PROTOCOL_ID,
+};
+use bevy_garage_car::{esp_system, spawn_car, Car, CarWheels, Wheel};
+use bevy_rapier3d::prelude::*;
+use bevy_renet::{
+    renet::{
+        transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig},
+        ClientId, RenetServer, ServerEvent,
+    },
+    transport::NetcodeServerPlugin,
+    RenetServerPlugin,
+};
+use health::{Destroyed, Health, HealthPlugin};
+use interest::{snapshot_budget, ClientViews};
+use projectile::{AttackRequest, ProjectilePlugin, Weapon};
+use respawn::{Recovery, RespawnPlugin, RespawnReason, RespawnRequest};
+pub use settings::ServerSettings;
+use spawn::{SpawnPlugin, Spawner};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::UdpSocket,
+    time::{Duration, SystemTime},
+};
+
+mod health;
+mod interest;
+mod projectile;
+mod respawn;
+pub mod settings;
+mod spawn;
+
+#[derive(Debug, Default, Resource)]
+pub struct ServerLobby {
+    pub players: HashMap<ClientId, Entity>,
+}
+
+/// Malformed messages received per client, see `MAX_PROTOCOL_VIOLATIONS`.
+#[derive(Debug, Default, Resource)]
+pub struct ProtocolViolations(pub HashMap<ClientId, u32>);
+
+impl ProtocolViolations {
+    /// Records a violation and tells whether the client should be dropped.
+    fn record(&mut self, client_id: ClientId) -> bool {
+        let count = self.0.entry(client_id).or_default();
+        *count += 1;
+        *count >= MAX_PROTOCOL_VIOLATIONS
+    }
+}
+
+/// Clients sent `ServerMessages::Rejected`, disconnected after `REJECTION_GRACE`
+/// so that the reason reaches them first.
+#[derive(Debug, Default, Resource)]
+pub struct Rejections(pub HashMap<ClientId, Timer>);
+
+const REJECTION_GRACE: Duration = Duration::from_millis(500);
+
+/// Inputs queued beyond this are dropped oldest first, so a client sending too fast
+/// can't pile up latency.
+const MAX_QUEUED_INPUTS: usize = 16;
+
+/// Inputs received but not simulated yet, oldest first. One is moved to the car's
+/// `PlayerInput` each tick, that is the sequence snapshots ack.
+#[derive(Debug, Default, Component)]
+pub struct InputQueue(VecDeque<PlayerInput>);
+
+impl InputQueue {
+    /// Newest sequence received, queued or already simulated.
+    fn last_sequence(&self, simulated: &PlayerInput) -> u32 {
+        self.0
+            .back()
+            .map_or(simulated.sequence, |input| input.sequence)
+    }
+
+    fn push(&mut self, input: PlayerInput) {
+        if self.0.len() == MAX_QUEUED_INPUTS {
+            self.0.pop_front();
+        }
+        self.0.push_back(input);
+    }
+}
+
+fn reject(
+    server: &mut RenetServer,
+    rejections: &mut Rejections,
+    client_id: ClientId,
+    reason: String,
+) {
+    println!("Client {} rejected: {}", client_id, reason);
+    let message = bincode::serialize(&ServerMessages::Rejected { reason }).unwrap();
+    server.send_message(client_id, ServerChannel::ServerMessages, message);
+    rejections
+        .0
+        .insert(client_id, Timer::new(REJECTION_GRACE, TimerMode::Once));
+}
+
+/// Keeps the ids the token service must not hand out in step with the transport.
+fn connected_clients_system(
+    mut server_events: EventReader<ServerEvent>,
+    connected: Res<ConnectedClients>,
+) {
+    for event in server_events.read() {
+        match event {
+            ServerEvent::ClientConnected { client_id } => connected.insert(client_id.raw()),
+            ServerEvent::ClientDisconnected { client_id, .. } => connected.remove(client_id.raw()),
+        }
+    }
+}
+
+fn rejection_system(
+    time: Res<Time>,
+    mut server: ResMut<RenetServer>,
+    mut rejections: ResMut<Rejections>,
+) {
+    rejections.0.retain(|client_id, timer| {
+        if timer.tick(time.delta()).finished() {
+            server.disconnect(*client_id);
+            return false;
+        }
+        true
+    });
+}
+
+/// The error is ready to print, e.g. the port is taken or the private key is missing.
+/// A listen server without a key accepts anyone, its own player doesn't need one.
+fn new_renet_server(
+    settings: &ServerSettings,
+    listen: bool,
+    connected: &ConnectedClients,
+) -> Result<(RenetServer, NetcodeServerTransport), String> {
+    let server = RenetServer::new(settings.network.connection_config());
+
+    let public_addresses = settings.public_addresses();
+    println!("socket binding to {}", settings.bind);
+    let socket = UdpSocket::bind(settings.bind)
+        .map_err(|e| format!("can't bind {}: {}", settings.bind, e))?;
+    println!("socket bind");
+    let current_time: Duration = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    let private_key = if unsecure_from_env() {
+        println!("RENET_UNSECURE set, accepting unauthenticated clients");
+        None
+    } else if let Some(private_key) = private_key_from_env()? {
+        Some(private_key)
+    } else if listen {
+        println!(
+            "No private key set, accepting unauthenticated clients on {}",
+            settings.bind
+        );
+        None
+    } else {
+        return Err(
+            "RENET_PRIVATE_KEY or RENET_PRIVATE_KEY_FILE required, or set RENET_UNSECURE=1 for dev"
+                .to_string(),
+        );
+    };
+    let authentication = match private_key {
+        Some(private_key) => {
+            if let Ok(token_addr) = std::env::var("RENET_TOKEN_SOCKET") {
+                let listener = std::net::TcpListener::bind(&token_addr)
+                    .map_err(|e| format!("can't bind token service {}: {}", token_addr, e))?;
+                println!("token service listening on {}", token_addr);
+                let issuer = TokenIssuer::new(private_key, public_addresses.clone());
+                let connected = connected.clone();
+                std::thread::spawn(move || serve_tokens(listener, issuer, connected));
+            }
+            ServerAuthentication::Secure { private_key }
+        }
+        None => ServerAuthentication::Unsecure,
+    };
+    let server_config = ServerConfig {
+        current_time,
+        max_clients: settings.max_clients,
+        protocol_id: PROTOCOL_ID,
+        public_addresses,
+        authentication,
+    };
+
+    let transport = NetcodeServerTransport::new(server_config, socket)
+        .map_err(|e| format!("can't start netcode transport: {}", e))?;
+
+    Ok((server, transport))
+}
+
+/// Dedicated server, with a debug window when built with graphics.
+pub fn run(settings: ServerSettings) -> Result<(), String> {
+    let mut app = build_app(settings, None)?;
+    println!("before app run");
+    app.run();
+    println!("after app run");
+    Ok(())
+}
+
+/// Server app, `local_client` is the in-process player of a listen server
+/// which runs without a window of its own next to the client's.
+pub fn build_app(
+    settings: ServerSettings,
+    local_client: Option<MemoryServerTransport>,
+) -> Result<App, String> {
+    let mut app = App::new();
+    println!("{:?}", settings);
+    let run_loop = bevy::app::ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+        1. / settings.tick_rate as f64,
+    ));
+    #[cfg(feature = "graphics")]
+    {
+        app.insert_resource(bevy_garage_car::CarRes {
+            show_rays: true,
+            ..default()
+        });
+        if local_client.is_none() {
+            app.add_plugins((
+                DefaultPlugins.set(WindowPlugin {
+                    primary_window: Some(Window {
+                        title: "Bevy Garage renet server".to_string(),
+                        resolution: bevy::window::WindowResolution::new(640., 240.),
+                        canvas: Some("#bevy-garage".to_string()),
+                        ..default()
+                    }),
+                    ..default()
+                }),
+                RapierDebugRenderPlugin::default(),
+                bevy_egui::EguiPlugin,
+            ));
+            app.add_systems(Startup, setup_simple_camera);
+            app.add_systems(Update, (update_visulizer_system,));
+        } else {
+            // Car scenes still load, but nothing renders and the client owns the event loop
+            app.add_plugins((
+                DefaultPlugins
+                    .set(WindowPlugin {
+                        primary_window: None,
+                        exit_condition: bevy::window::ExitCondition::DontExit,
+                        close_when_requested: false,
+                    })
+                    .set(bevy::render::RenderPlugin {
+                        render_creation: bevy::render::settings::WgpuSettings {
+                            backends: None,
+                            ..default()
+                        }
+                        .into(),
+                        ..default()
+                    })
+                    .disable::<bevy::winit::WinitPlugin>()
+                    .disable::<bevy::audio::AudioPlugin>()
+                    .disable::<bevy::gilrs::GilrsPlugin>(),
+                run_loop,
+            ));
+        }
+        app.add_systems(Startup, bevy_garage_car::car_start_system);
+        app.insert_resource(renet_visualizer::RenetServerVisualizer::<200>::default());
+    }
+    #[cfg(not(feature = "graphics"))]
+    app.add_plugins(MinimalPlugins.set(run_loop));
+
+    app.add_plugins((
+        RenetServerPlugin,
+        NetcodeServerPlugin,
+        MemoryServerPlugin,
+        ProjectilePlugin,
+        HealthPlugin,
+        RespawnPlugin,
+        SpawnPlugin,
+        RapierPhysicsPlugin::<NoUserData>::default().in_fixed_schedule(),
+        FrameTimeDiagnosticsPlugin,
+        LogDiagnosticsPlugin::default(),
+    ));
+
+    app.insert_resource(Time::<Fixed>::from_hz(settings.tick_rate as f64));
+    app.insert_resource(RapierConfiguration {
+        timestep_mode: TimestepMode::Fixed {
+            dt: 1. / settings.tick_rate as f32,
+            substeps: 5,
+        },
+        ..default()
+    });
+    app.insert_resource(settings.physics);
+    app.insert_resource(Level::load(&settings.level)?);
+    app.insert_resource(ServerLobby::default());
+    app.insert_resource(ProtocolViolations::default());
+    app.insert_resource(Rejections::default());
+    app.insert_resource(ServerTick::default());
+    app.insert_resource(ClientViews::default());
+
+    let connected = ConnectedClients::default();
+    let (server, transport) = new_renet_server(&settings, local_client.is_some(), &connected)?;
+    app.insert_resource(server).insert_resource(transport);
+    app.insert_resource(connected);
+    app.insert_resource(settings);
+
+    app.add_systems(
+        Update,
+        (
+            server_update_system,
+            rejection_system,
+            connected_clients_system,
+        ),
+    );
+    // One simulation tick: inputs to cars, ESP, Rapier step, then maybe a snapshot
+    app.add_systems(
+        FixedUpdate,
+        (server_tick_system, move_players_system, esp_system)
+            .chain()
+            .before(PhysicsSet::SyncBackend),
+    );
+    app.add_systems(
+        FixedUpdate,
+        server_network_sync
+            .after(PhysicsSet::Writeback)
+            .run_if(snapshot_tick),
+    );
+    app.add_systems(PostUpdate, wheel_anchors_system);
+    app.add_systems(Startup, (physics_settings_system, setup_level));
+    if let Some(local_client) = local_client {
+        app.insert_resource(local_client);
+    }
+    Ok(app)
+}
+
+/// The car owning `entity`, which may be its body, one of its children or one of its wheels.
+fn car_of(
+    entity: Entity,
+    cars: &Query<(Entity, &CarWheels), With<Player>>,
+    parents: &Query<&Parent>,
+) -> Option<Entity> {
+    let mut current = entity;
+    loop {
+        if cars.contains(current) {
+            return Some(current);
+        }
+        match parents.get(current) {
+            Ok(parent) => current = parent.get(),
+            Err(_) => break,
+        }
+    }
+    cars.iter()
+        .find(|(_, wheels)| wheels.entities.contains(&entity))
+        .map(|(car, _)| car)
+}
+
+/// Whether `tick` is at or past `deadline`, across `ServerTick` wrapping.
+fn reached(tick: u32, deadline: u32) -> bool {
+    (tick.wrapping_sub(deadline) as i32) >= 0
+}
+
+/// The profile the client connected with, an error if its user data doesn't decode.
+fn client_profile(
+    client_id: ClientId,
+    netcode: Option<&NetcodeServerTransport>,
+    memory: Option<&MemoryServerTransport>,
+) -> Result<PlayerProfile, String> {
+    let user_data = match memory {
+        Some(memory) if memory.client_id == client_id => Some(memory.user_data),
+        _ => netcode.and_then(|transport| transport.user_data(client_id)),
+    };
+    let user_data = user_data.ok_or("no user data")?;
+    PlayerProfile::from_user_data(&user_data).map_err(|e| e.to_string())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn server_update_system(
+    mut server_events: EventReader<ServerEvent>,
+    mut cmd: Commands,
+    mut lobby: ResMut<ServerLobby>,
+    mut violations: ResMut<ProtocolViolations>,
+    mut rejections: ResMut<Rejections>,
+    mut views: ResMut<ClientViews>,
+    mut server: ResMut<RenetServer>,
+    (mut attacks, mut respawns): (EventWriter<AttackRequest>, EventWriter<RespawnRequest>),
+    spawner: Spawner,
+    settings: Res<ServerSettings>,
+    level: Res<Level>,
+    // One parameter, systems take at most 16
+    (netcode, memory): (
+        Option<Res<NetcodeServerTransport>>,
+        Option<Res<MemoryServerTransport>>,
+    ),
+    players: Query<(Entity, &Player, &Transform, &Health)>,
+    mut inputs: Query<(&PlayerInput, &mut InputQueue)>,
+    #[cfg(feature = "graphics")] car_res: Res<bevy_garage_car::CarRes>,
+    #[cfg(feature = "graphics")] mut visualizer: ResMut<
+        renet_visualizer::RenetServerVisualizer<200>,
+    >,
+) {
+    let mut spawned = vec![];
+    for event in server_events.read() {
+        match event {
+            ServerEvent::ClientConnected { client_id } => {
+                #[cfg(feature = "graphics")]
+                visualizer.add_client(client_id.to_owned());
+                // Before the handshake so a rejected client never loads our level.
+                // Keep the player already in the lobby rather than silently replacing it.
+                if lobby.players.contains_key(client_id) {
+                    let reason = format!("client id {} already in use", client_id);
+                    reject(&mut server, &mut rejections, *client_id, reason);
+                    continue;
+                }
+                let profile =
+                    match client_profile(*client_id, netcode.as_deref(), memory.as_deref()) {
+                        Ok(profile) => profile,
+                        Err(e) => {
+                            let reason = format!("invalid player profile: {}", e);
+                            reject(&mut server, &mut rejections, *client_id, reason);
+                            continue;
+                        }
+                    };
+                let message = bincode::serialize(&ServerMessages::Handshake(
+                    ProtocolVersion::current(&level),
+                ))
+                .unwrap();
+                server.send_message(client_id.to_owned(), ServerChannel::ServerMessages, message);
+                // A few KB, simpler to always send than to ask, clients with the same hash skip it
+                let message = bincode::serialize(&ServerMessages::Level {
+                    ron: level.to_ron(),
+                })
+                .unwrap();
+                server.send_message(client_id.to_owned(), ServerChannel::ServerMessages, message);
+                println!("Player {} connected as '{}'.", client_id, profile.name);
+                let message = bincode::serialize(&ServerMessages::ServerInfo {
+                    tick_rate: settings.tick_rate,
+                    physics: settings.physics,
+                    max_health: settings.damage.max_health,
+                })
+                .unwrap();
+                server.send_message(client_id.to_owned(), ServerChannel::ServerMessages, message);
+
+                for (entity, player, transform, health) in players.iter() {
+                    let message = bincode::serialize(&ServerMessages::PlayerCreate {
+                        id: player.id,
+                        entity,
+                        translation: transform.translation.into(),
+                        rotation: transform.rotation.into(),
+                        profile: player.profile.clone(),
+                    })
+                    .unwrap();
+                    server.send_message(
+                        client_id.to_owned(),
+                        ServerChannel::ServerMessages,
+                        message,
+                    );
+                    let message = bincode::serialize(&ServerMessages::PlayerHealth {
+                        id: player.id,
+                        health: health.current,
+                    })
+                    .unwrap();
+                    server.send_message(
+                        client_id.to_owned(),
+                        ServerChannel::ServerMessages,
+                        message,
+                    );
+                }
+                let transform = spawner.find(&spawned, &[]);
+                spawned.push(transform.translation);
+                let player_entity = spawn_car(
+                    &mut cmd,
+                    #[cfg(feature = "graphics")]
+                    &car_res.car_scene.as_ref().unwrap(),
+                    #[cfg(feature = "graphics")]
+                    &car_res.wheel_scene.as_ref().unwrap(),
+                    false,
+                    transform,
+                );
+                cmd.entity(player_entity)
+                    .insert(Player {
+                        id: client_id.to_owned(),
+                        profile: profile.clone(),
+                    })
+                    .insert((
+                        PlayerInput::default(),
+                        InputQueue::default(),
+                        Weapon::default(),
+                        Recovery::default(),
+                        spawner.protection(),
+                        Health {
+                            current: settings.damage.max_health,
+                        },
+                    ));
+
+                lobby.players.insert(client_id.to_owned(), player_entity);
+
+                let message = bincode::serialize(&ServerMessages::PlayerCreate {
+                    id: client_id.to_owned(),
+                    entity: player_entity,
+                    translation: transform.translation.into(),
+                    rotation: transform.rotation.into(),
+                    profile,
+                })
+                .unwrap();
+                server.broadcast_message(ServerChannel::ServerMessages, message);
+            }
+            ServerEvent::ClientDisconnected { client_id, reason } => {
+                println!("Player {} disconnected: {}", client_id, reason);
+                #[cfg(feature = "graphics")]
+                visualizer.remove_client(client_id.to_owned());
+                violations.0.remove(client_id);
+                views.0.remove(client_id);
+                rejections.0.remove(client_id);
+                if let Some(player_entity) = lobby.players.remove(&client_id) {
+                    cmd.entity(player_entity).despawn();
+                }
+
+                let message = bincode::serialize(&ServerMessages::PlayerRemove {
+                    id: client_id.to_owned(),
+                })
+                .unwrap();
+                server.broadcast_message(ServerChannel::ServerMessages, message);
+            }
+        }
+    }
+
+    // A client disconnected for violations gets nothing more processed
+    'clients: for client_id in server.clients_id() {
+        let player_entity = lobby.players.get(&client_id).copied();
+        while let Some(message) = server.receive_message(client_id, ClientChannel::Command) {
+            let command: PlayerCommand = match decode_client_message(&message) {
+                Ok(command) => command,
+                Err(e) => {
+                    println!("Client {} sent bad command: {}", client_id, e);
+                    if violations.record(client_id) {
+                        println!("Client {} disconnected: protocol violations", client_id);
+                        server.disconnect(client_id);
+                        continue 'clients;
+                    }
+                    continue;
+                }
+            };
+            match command {
+                PlayerCommand::BasicAttack { cast_at } => {
+                    if let Some(car) = player_entity {
+                        attacks.send(AttackRequest { car, cast_at });
+                    }
+                }
+                PlayerCommand::Respawn => {
+                    if let Some(car) = player_entity {
+                        respawns.send(RespawnRequest {
+                            car,
+                            reason: RespawnReason::Command,
+                        });
+                    }
+                }
+            }
+        }
+        let mut queue = player_entity.and_then(|entity| inputs.get_mut(entity).ok());
+        let mut last_sequence = queue
+            .as_ref()
+            .map_or(0, |(simulated, queue)| queue.last_sequence(simulated));
+        while let Some(message) = server.receive_message(client_id, ClientChannel::Input) {
+            let input: PlayerInput = match decode_client_message(&message) {
+                Ok(input) => input,
+                Err(e) => {
+                    println!("Client {} sent bad input: {}", client_id, e);
+                    if violations.record(client_id) {
+                        println!("Client {} disconnected: protocol violations", client_id);
+                        server.disconnect(client_id);
+                        continue 'clients;
+                    }
+                    continue;
+                }
+            };
+            // Out of order or duplicate, a newer input is queued already
+            if input.sequence <= last_sequence {
+                continue;
+            }
+            last_sequence = input.sequence;
+            if let Some((_, queue)) = queue.as_mut() {
+                queue.push(input);
+            }
+        }
+    }
+}
+
+fn server_tick_system(mut tick: ResMut<ServerTick>) {
+    tick.0 = tick.0.wrapping_add(1);
+}
+
+fn snapshot_tick(tick: Res<ServerTick>, settings: Res<ServerSettings>) -> bool {
+    tick.0 % settings.send_interval() == 0
+}
+
+#[cfg(feature = "graphics")]
+fn update_visulizer_system(
+    mut egui_contexts: bevy_egui::EguiContexts,
+    mut visualizer: ResMut<renet_visualizer::RenetServerVisualizer<200>>,
+    server: Res<RenetServer>,
+) {
+    visualizer.update(&server);
+    visualizer.show_window(egui_contexts.ctx_mut());
+}
+
+#[allow(clippy::type_complexity)]
+fn server_network_sync(
+    mut server: ResMut<RenetServer>,
+    tick: Res<ServerTick>,
+    lobby: Res<ServerLobby>,
+    settings: Res<ServerSettings>,
+    mut views: ResMut<ClientViews>,
+    mut tr_set: ParamSet<(
+        Query<(Entity, &Transform, &CarWheels, &WheelAnchors), With<Player>>,
+        Query<&Transform, With<Wheel>>,
+        Query<(&PlayerInput, Option<&Velocity>)>,
+    )>,
+) {
+    let mut cars: Vec<(Entity, Transform, [Entity; 4], WheelAnchors)> = vec![];
+    for (entity, transform, wheels, anchors) in tr_set.p0().iter() {
+        cars.push((entity, *transform, wheels.entities, *anchors));
+    }
+
+    let mut current = WorldState::default();
+    let mut positions: HashMap<Entity, Vec3> = HashMap::new();
+    for (entity, transform, wheels, anchors) in cars {
+        let wheels_query = tr_set.p1();
+        // Wheels can be gone for a tick, e.g. while the car is despawned on disconnect
+        let Ok(wheels) = wheels_query.get_many(wheels) else {
+            continue;
+        };
+        positions.insert(entity, transform.translation);
+        current.insert(entity, EntityState::new(&transform, wheels, &anchors));
+    }
+
+    let budget = snapshot_budget(&settings.network);
+    for client_id in server.clients_id() {
+        let player_entity = lobby.players.get(&client_id).copied();
+        let (input, velocity) = player_entity
+            .and_then(|entity| {
+                tr_set
+                    .p2()
+                    .get(entity)
+                    .ok()
+                    .map(|(input, velocity)| (Some(*input), velocity.copied()))
+            })
+            .unwrap_or_default();
+        let ack_input = input.map(|input| input.sequence).unwrap_or(0);
+        let view = views.0.entry(client_id).or_default();
+        // Delta against the newest snapshot the client confirmed, full if that's too old
+        let baseline: Option<(u32, WorldState)> = input.and_then(|input| {
+            view.history
+                .get(input.ack_tick)
+                .map(|baseline| (input.ack_tick, baseline.clone()))
+        });
+        let mut client_state = baseline
+            .as_ref()
+            .map(|(_, baseline)| baseline.clone())
+            .unwrap_or_default();
+        client_state.retain(|entity, _| current.contains_key(entity));
+
+        let candidates: Vec<(Entity, EntityState, Vec3)> = current
+            .iter()
+            .filter(|(entity, state)| client_state.get(entity) != Some(state))
+            .map(|(entity, state)| (*entity, *state, positions[entity]))
+            .collect();
+        let changed: Vec<Entity> = candidates.iter().map(|(entity, _, _)| *entity).collect();
+        let baseline_tick = baseline.as_ref().map(|(baseline_tick, _)| *baseline_tick);
+        let viewer = player_entity.and_then(|entity| positions.get(&entity).map(|p| (entity, *p)));
+        let mut updated = vec![];
+        for (entity, state) in view.select(tick.0, baseline_tick, viewer, candidates, budget) {
+            client_state.insert(entity, state);
+            updated.push(entity);
+        }
+
+        let baseline = baseline
+            .as_ref()
+            .map(|(baseline_tick, baseline)| (*baseline_tick, baseline));
+        let mut networked_entities =
+            NetworkedEntities::encode(tick.0, ack_input, &client_state, baseline);
+        networked_entities.velocity =
+            velocity.map(|velocity| (velocity.linvel.into(), velocity.angvel.into()));
+        networked_entities.held_back = changed
+            .into_iter()
+            .filter(|entity| !updated.contains(entity))
+            .collect();
+        let sync_message = bincode::serialize(&networked_entities).unwrap();
+        server.send_message(client_id, ServerChannel::NetworkedEntities, sync_message);
+        view.insert(tick.0, client_state, baseline_tick, &updated);
+    }
+}
+
+/// Simulates one queued input per tick, the client predicted each one for a tick too.
+/// With none queued the last input repeats and its sequence stays acked.
+fn move_players_system(
+    mut query: Query<(&mut InputQueue, &mut PlayerInput, &mut Car, Has<Destroyed>)>,
+) {
+    for (mut queue, mut input, mut car, destroyed) in query.iter_mut() {
+        // Wrecks still use up their inputs, they'd be stale after the respawn
+        if let Some(next) = queue.0.pop_front() {
+            *input = next;
+        }
+        if destroyed {
+            continue;
+        }
+        apply_player_input(&input, &mut car);
+    }
+}
+
+#[cfg(feature = "graphics")]
+pub fn setup_simple_camera(mut commands: Commands) {
+    commands.spawn(Camera3dBundle {
+        transform: Transform::from_xyz(-20.5, 30.0, 20.5).looking_at(Vec3::ZERO, Vec3::Y),
+        ..Default::default()
+    });
+}
@@ -0,0 +1,191 @@
+//! Projectiles fired with `PlayerCommand::BasicAttack`: simulated by Rapier on the server only,
+//! clients are told when one spawns and when it's gone.
+use bevy::prelude::*;
+use bevy_garage_arena_lib::{Player, ServerChannel, ServerMessages, ServerTick};
+use bevy_garage_car::CarWheels;
+use bevy_rapier3d::prelude::*;
+use bevy_renet::renet::RenetServer;
+use std::collections::HashMap;
+
+use crate::{car_of, move_players_system, reached, spawn::SpawnProtection, ServerSettings};
+
+/// Projectiles leave this high above the car's origin, and this far from it towards the target.
+const MUZZLE_HEIGHT: f32 = 1.;
+const MUZZLE_DISTANCE: f32 = 3.;
+
+/// A player's `BasicAttack`, fired by `fire_system` unless the car's weapon is cooling down.
+#[derive(Debug, Event)]
+pub struct AttackRequest {
+    pub car: Entity,
+    pub cast_at: Vec3,
+}
+
+/// A projectile hit a car other than the one that fired it.
+#[derive(Debug, Event)]
+pub struct ProjectileHit {
+    pub owner: Entity,
+    pub target: Entity,
+}
+
+/// Tick at which the car can fire again.
+#[derive(Debug, Default, Component)]
+pub struct Weapon {
+    ready_tick: u32,
+}
+
+#[derive(Debug, Component)]
+struct Projectile {
+    owner: Entity,
+    direction: Vec3,
+    expires_tick: u32,
+    // Hit something this tick, despawned already
+    spent: bool,
+}
+
+fn despawn_projectile(cmd: &mut Commands, server: &mut RenetServer, entity: Entity) {
+    cmd.entity(entity).despawn();
+    let message = bincode::serialize(&ServerMessages::ProjectileDespawn { entity }).unwrap();
+    server.broadcast_message(ServerChannel::ServerMessages, message);
+}
+
+fn fire_system(
+    mut cmd: Commands,
+    mut requests: EventReader<AttackRequest>,
+    mut server: ResMut<RenetServer>,
+    tick: Res<ServerTick>,
+    settings: Res<ServerSettings>,
+    mut cars: Query<(&Player, &Transform, Option<&Velocity>, &mut Weapon)>,
+) {
+    let projectile = settings.projectile;
+    for request in requests.read() {
+        let Ok((player, transform, car_velocity, mut weapon)) = cars.get_mut(request.car) else {
+            continue;
+        };
+        if !reached(tick.0, weapon.ready_tick) {
+            continue;
+        }
+        let origin = transform.translation + Vec3::Y * MUZZLE_HEIGHT;
+        // Also rejects NaN sent by a broken client
+        let Some(direction) = (request.cast_at - origin).try_normalize() else {
+            continue;
+        };
+        weapon.ready_tick = tick.0.wrapping_add(settings.ticks(projectile.cooldown));
+        // Protection is for getting away, not for attacking
+        cmd.entity(request.car).remove::<SpawnProtection>();
+
+        let translation = origin + direction * MUZZLE_DISTANCE;
+        let velocity = car_velocity.map_or(Vec3::ZERO, |velocity| velocity.linvel)
+            + direction * projectile.speed;
+        let entity = cmd
+            .spawn((
+                TransformBundle::from_transform(Transform::from_translation(translation)),
+                RigidBody::Dynamic,
+                Collider::ball(projectile.radius),
+                // Hits are resolved by `projectile_hit_system`, not by contacts
+                Sensor,
+                GravityScale(0.),
+                Velocity::linear(velocity),
+                ActiveEvents::COLLISION_EVENTS,
+                Projectile {
+                    owner: request.car,
+                    direction,
+                    expires_tick: tick.0.wrapping_add(settings.ticks(projectile.lifetime)),
+                    spent: false,
+                },
+            ))
+            .id();
+        let message = bincode::serialize(&ServerMessages::ProjectileSpawn {
+            entity,
+            owner: player.id,
+            translation: translation.into(),
+            velocity: velocity.into(),
+        })
+        .unwrap();
+        server.broadcast_message(ServerChannel::ServerMessages, message);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn projectile_hit_system(
+    mut cmd: Commands,
+    mut collisions: EventReader<CollisionEvent>,
+    mut hits: EventWriter<ProjectileHit>,
+    mut server: ResMut<RenetServer>,
+    settings: Res<ServerSettings>,
+    mut projectiles: Query<&mut Projectile>,
+    cars: Query<(Entity, &CarWheels), With<Player>>,
+    parents: Query<&Parent>,
+) {
+    let mut impulses: HashMap<Entity, Vec3> = HashMap::new();
+    for event in collisions.read() {
+        let CollisionEvent::Started(a, b, _) = *event else {
+            continue;
+        };
+        let (entity, other) = if projectiles.contains(a) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let Ok(mut projectile) = projectiles.get_mut(entity) else {
+            continue;
+        };
+        if projectile.spent {
+            continue;
+        }
+        let target = car_of(other, &cars, &parents);
+        // Leaving the car that fired it
+        if target == Some(projectile.owner) {
+            continue;
+        }
+        if let Some(target) = target {
+            let impulse = projectile.direction * settings.projectile.impulse;
+            *impulses.entry(target).or_default() += impulse;
+            hits.send(ProjectileHit {
+                owner: projectile.owner,
+                target,
+            });
+        }
+        projectile.spent = true;
+        despawn_projectile(&mut cmd, &mut server, entity);
+    }
+    for (target, impulse) in impulses {
+        cmd.entity(target).insert(ExternalImpulse {
+            impulse,
+            ..default()
+        });
+    }
+}
+
+fn projectile_expiry_system(
+    mut cmd: Commands,
+    mut server: ResMut<RenetServer>,
+    tick: Res<ServerTick>,
+    projectiles: Query<(Entity, &Projectile)>,
+) {
+    for (entity, projectile) in projectiles.iter() {
+        if !projectile.spent && reached(tick.0, projectile.expires_tick) {
+            despawn_projectile(&mut cmd, &mut server, entity);
+        }
+    }
+}
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AttackRequest>();
+        app.add_event::<ProjectileHit>();
+        app.add_systems(
+            FixedUpdate,
+            fire_system
+                .after(move_players_system)
+                .before(PhysicsSet::SyncBackend),
+        );
+        app.add_systems(
+            FixedUpdate,
+            (projectile_hit_system, projectile_expiry_system)
+                .chain()
+                .after(PhysicsSet::Writeback),
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Moving a player's car back to a spawn point, upright and at rest,
+//! clients are told so they snap to it instead of interpolating.
+use bevy::prelude::*;
+use bevy_garage_arena_lib::{Player, ServerChannel, ServerMessages, ServerTick, SpawnPoint};
+use bevy_garage_car::{CarWheels, Wheel};
+use bevy_rapier3d::prelude::*;
+use bevy_renet::renet::RenetServer;
+use std::collections::HashSet;
+
+use crate::{
+    health::{Destroyed, Health},
+    reached,
+    spawn::Spawner,
+    ServerSettings,
+};
+
+/// Up vector height under which a car is on its roof or side, about 70°.
+const FLIPPED_UP: f32 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RespawnReason {
+    Destroyed,
+    /// `PlayerCommand::Respawn`, limited by `RespawnSettings::cooldown`.
+    Command,
+    Stuck,
+}
+
+#[derive(Debug, Event)]
+pub struct RespawnRequest {
+    pub car: Entity,
+    pub reason: RespawnReason,
+}
+
+/// When the player can ask for a respawn again, and how long the car has been stuck.
+#[derive(Debug, Default, Component)]
+pub struct Recovery {
+    ready_tick: u32,
+    stuck_ticks: u32,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn respawn_system(
+    mut cmd: Commands,
+    mut requests: EventReader<RespawnRequest>,
+    mut server: ResMut<RenetServer>,
+    tick: Res<ServerTick>,
+    settings: Res<ServerSettings>,
+    spawner: Spawner,
+    mut cars: Query<
+        (
+            &Player,
+            &CarWheels,
+            &mut Transform,
+            &mut Recovery,
+            Option<&mut Health>,
+            Has<Destroyed>,
+        ),
+        (Without<Wheel>, Without<SpawnPoint>),
+    >,
+    mut wheels: Query<&mut Transform, (With<Wheel>, Without<SpawnPoint>)>,
+    mut velocities: Query<&mut Velocity>,
+) {
+    let mut respawned = HashSet::new();
+    let mut spawned = vec![];
+    for request in requests.read() {
+        if !respawned.insert(request.car) {
+            continue;
+        }
+        let Ok((player, car_wheels, mut transform, mut recovery, health, destroyed)) =
+            cars.get_mut(request.car)
+        else {
+            continue;
+        };
+        // A wreck waits for its respawn delay, and players can't teleport at will
+        if request.reason == RespawnReason::Command
+            && (destroyed || !reached(tick.0, recovery.ready_tick))
+        {
+            continue;
+        }
+        recovery.ready_tick = tick
+            .0
+            .wrapping_add(settings.ticks(settings.respawn.cooldown));
+        recovery.stuck_ticks = 0;
+        println!("Player {} respawned: {:?}.", player.id, request.reason);
+        let own_colliders: Vec<Entity> = car_wheels
+            .entities
+            .into_iter()
+            .chain([request.car])
+            .collect();
+        let spawn = spawner.find(&spawned, &own_colliders);
+        spawned.push(spawn.translation);
+        cmd.entity(request.car).insert(spawner.protection());
+        // Wheels keep their pose relative to the car, so the joints don't pull them back
+        let to_spawn = spawn.compute_matrix() * transform.compute_matrix().inverse();
+        for wheel in car_wheels.entities {
+            if let Ok(mut wheel_transform) = wheels.get_mut(wheel) {
+                *wheel_transform =
+                    Transform::from_matrix(to_spawn * wheel_transform.compute_matrix());
+            }
+        }
+        *transform = spawn;
+        for entity in car_wheels.entities.into_iter().chain([request.car]) {
+            if let Ok(mut velocity) = velocities.get_mut(entity) {
+                *velocity = Velocity::zero();
+            }
+        }
+
+        if let Some(mut health) = health {
+            health.current = settings.damage.max_health;
+            let message = bincode::serialize(&ServerMessages::PlayerHealth {
+                id: player.id,
+                health: health.current,
+            })
+            .unwrap();
+            server.broadcast_message(ServerChannel::ServerMessages, message);
+        }
+        let message = bincode::serialize(&ServerMessages::PlayerRespawn {
+            id: player.id,
+            tick: tick.0,
+            translation: spawn.translation.into(),
+            rotation: spawn.rotation.into(),
+        })
+        .unwrap();
+        server.broadcast_message(ServerChannel::ServerMessages, message);
+    }
+}
+
+/// Respawns cars left on their roof or side, players can't get those back on their wheels.
+fn stuck_system(
+    mut respawns: EventWriter<RespawnRequest>,
+    settings: Res<ServerSettings>,
+    mut cars: Query<
+        (Entity, &Transform, Option<&Velocity>, &mut Recovery),
+        (With<Player>, Without<Destroyed>),
+    >,
+) {
+    let stuck_ticks = settings.ticks(settings.respawn.stuck_time);
+    for (entity, transform, velocity, mut recovery) in cars.iter_mut() {
+        let flipped = transform.up().y < FLIPPED_UP;
+        let still =
+            velocity.is_none_or(|velocity| velocity.linvel.length() < settings.respawn.stuck_speed);
+        if !(flipped && still) {
+            recovery.stuck_ticks = 0;
+            continue;
+        }
+        recovery.stuck_ticks += 1;
+        if recovery.stuck_ticks >= stuck_ticks {
+            recovery.stuck_ticks = 0;
+            respawns.send(RespawnRequest {
+                car: entity,
+                reason: RespawnReason::Stuck,
+            });
+        }
+    }
+}
+
+pub struct RespawnPlugin;
+
+impl Plugin for RespawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RespawnRequest>();
+        app.add_systems(
+            FixedUpdate,
+            (stuck_system, respawn_system)
+                .chain()
+                .before(PhysicsSet::SyncBackend),
+        );
+    }
+}
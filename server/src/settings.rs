@@ -0,0 +1,427 @@
+use bevy::prelude::*;
+use bevy_garage_arena_lib::{
+    level::DEFAULT_LEVEL, Level, NetworkLimits, PhysicsSettings, DEFAULT_TICK_RATE, LEVEL_SIZE,
+    SPAWN_HEIGHT,
+};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+/// Read when no `--config` is given and the file exists in the working directory.
+const DEFAULT_CONFIG_FILE: &str = "server.ron";
+/// Netcode can't track more clients than this.
+const MAX_CLIENTS_LIMIT: usize = 1024;
+
+const USAGE: &str = "Usage: bevy_garage_arena_server [OPTIONS]
+
+Options:
+  --config <PATH>       RON settings file [default: server.ron if present]
+  --bind <ADDR>         UDP address to bind [default: 127.0.0.1:5000]
+  --public-addr <ADDR>  Address clients connect to, repeat for several [default: bind address]
+  --max-clients <N>     Players allowed at once [default: 64]
+  --tick-rate <HZ>      Simulation ticks per second [default: 60]
+  --send-rate <HZ>      Snapshots per second [default: tick rate]
+  --level <NAME>        Level in assets/levels to load [default: arena]
+  --help                Print this help";
+
+/// Why `ServerSettings::from_args` gave no settings, printing it shows the usage or the problem.
+#[derive(Debug)]
+pub enum SettingsError {
+    /// `--help` was given.
+    Help,
+    Invalid(String),
+}
+
+impl SettingsError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SettingsError::Help => 0,
+            SettingsError::Invalid(_) => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SettingsError::Help => write!(f, "{}", USAGE),
+            SettingsError::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<String> for SettingsError {
+    fn from(e: String) -> Self {
+        SettingsError::Invalid(e)
+    }
+}
+
+/// Cars spawn at the level's spawn points, see `spawn`. When all are taken they spawn
+/// at random in a square of `size` m centered on the origin, `height` m up.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpawnArea {
+    pub size: f32,
+    pub height: f32,
+    /// Seconds a freshly spawned car takes no damage, or until it fires.
+    pub protection: f32,
+}
+
+impl Default for SpawnArea {
+    fn default() -> Self {
+        Self {
+            size: 40.,
+            height: SPAWN_HEIGHT,
+            protection: 3.,
+        }
+    }
+}
+
+impl SpawnArea {
+    pub fn random_transform(&self) -> Transform {
+        Transform::from_xyz(
+            (fastrand::f32() - 0.5) * self.size,
+            self.height,
+            (fastrand::f32() - 0.5) * self.size,
+        )
+    }
+}
+
+/// `PlayerCommand::BasicAttack` projectiles, see `projectile`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectileSettings {
+    /// m/s, on top of the velocity of the car firing.
+    pub speed: f32,
+    pub radius: f32,
+    /// Seconds before a projectile that hit nothing disappears.
+    pub lifetime: f32,
+    /// Seconds between two shots of the same car.
+    pub cooldown: f32,
+    /// N·s given to the car that's hit.
+    pub impulse: f32,
+}
+
+impl Default for ProjectileSettings {
+    fn default() -> Self {
+        Self {
+            speed: 60.,
+            radius: 0.25,
+            lifetime: 3.,
+            cooldown: 0.5,
+            impulse: 3000.,
+        }
+    }
+}
+
+/// Car health and what takes it away, see `health`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DamageSettings {
+    pub max_health: f32,
+    /// (contact force in N, damage) points of a hit against a wall, linear in between,
+    /// nothing below the first force and flat past the last.
+    pub wall_curve: Vec<(f32, f32)>,
+    /// Damage of a projectile hit.
+    pub projectile: f32,
+    /// Seconds a destroyed car waits before it respawns.
+    pub respawn_delay: f32,
+}
+
+impl Default for DamageSettings {
+    fn default() -> Self {
+        Self {
+            max_health: 100.,
+            wall_curve: vec![(100_000., 0.), (400_000., 10.), (1_500_000., 60.)],
+            projectile: 20.,
+            respawn_delay: 3.,
+        }
+    }
+}
+
+impl DamageSettings {
+    /// Weakest contact force that can do damage, below it Rapier doesn't report contacts.
+    pub fn min_wall_force(&self) -> f32 {
+        self.wall_curve
+            .first()
+            .map_or(f32::MAX, |(force, _)| *force)
+    }
+
+    pub fn wall_damage(&self, force: f32) -> f32 {
+        let mut previous: Option<(f32, f32)> = None;
+        for &(f1, d1) in self.wall_curve.iter() {
+            if force < f1 {
+                return previous.map_or(0., |(f0, d0)| d0 + (d1 - d0) * (force - f0) / (f1 - f0));
+            }
+            previous = Some((f1, d1));
+        }
+        previous.map_or(0., |(_, damage)| damage)
+    }
+}
+
+/// Getting cars back on their wheels, see `respawn`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RespawnSettings {
+    /// Seconds between two respawns the player asked for.
+    pub cooldown: f32,
+    /// Seconds a car stays flipped and still before it's respawned without asking.
+    pub stuck_time: f32,
+    /// m/s under which a flipped car counts as still.
+    pub stuck_speed: f32,
+}
+
+impl Default for RespawnSettings {
+    fn default() -> Self {
+        Self {
+            cooldown: 3.,
+            stuck_time: 4.,
+            stuck_speed: 1.,
+        }
+    }
+}
+
+/// Everything the server is run with: defaults, then the settings file, then CLI flags.
+#[derive(Debug, Clone, Serialize, Deserialize, Resource)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub bind: SocketAddr,
+    /// Advertised in connect tokens, the bind address if empty.
+    pub public_addresses: Vec<SocketAddr>,
+    pub max_clients: usize,
+    pub tick_rate: u32,
+    /// Snapshots per second, the tick rate if not set.
+    pub send_rate: Option<u32>,
+    pub level: String,
+    pub spawn: SpawnArea,
+    pub projectile: ProjectileSettings,
+    pub damage: DamageSettings,
+    pub respawn: RespawnSettings,
+    pub physics: PhysicsSettings,
+    pub network: NetworkLimits,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:5000".parse().unwrap(),
+            public_addresses: vec![],
+            max_clients: 64,
+            tick_rate: DEFAULT_TICK_RATE,
+            send_rate: None,
+            level: DEFAULT_LEVEL.to_string(),
+            spawn: SpawnArea::default(),
+            projectile: ProjectileSettings::default(),
+            damage: DamageSettings::default(),
+            respawn: RespawnSettings::default(),
+            physics: PhysicsSettings::default(),
+            network: NetworkLimits::default(),
+        }
+    }
+}
+
+fn parse_flag<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    let value = value.ok_or_else(|| format!("{} requires a value\n\n{}", flag, USAGE))?;
+    value
+        .parse()
+        .map_err(|e| format!("invalid value '{}' for {}: {}", value, flag, e))
+}
+
+impl ServerSettings {
+    /// Loads settings for the process arguments, the error is ready to print.
+    pub fn load() -> Result<Self, SettingsError> {
+        Self::from_args(std::env::args().skip(1))
+    }
+
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, SettingsError> {
+        let args: Vec<String> = args.into_iter().collect();
+        if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+            return Err(SettingsError::Help);
+        }
+
+        let config = args
+            .iter()
+            .position(|arg| arg == "--config")
+            .map(|i| parse_flag::<String>("--config", args.get(i + 1).cloned()))
+            .transpose()?;
+        let mut settings = match config {
+            Some(path) => Self::from_file(&path)?,
+            None if std::path::Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(DEFAULT_CONFIG_FILE)?
+            }
+            None => Self::default(),
+        };
+
+        let mut public_addresses = vec![];
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            match flag.as_str() {
+                "--config" => {
+                    args.next();
+                }
+                "--bind" => settings.bind = parse_flag(&flag, args.next())?,
+                "--public-addr" => public_addresses.push(parse_flag(&flag, args.next())?),
+                "--max-clients" => settings.max_clients = parse_flag(&flag, args.next())?,
+                "--tick-rate" => settings.tick_rate = parse_flag(&flag, args.next())?,
+                "--send-rate" => settings.send_rate = Some(parse_flag(&flag, args.next())?),
+                "--level" => settings.level = parse_flag(&flag, args.next())?,
+                _ => return Err(format!("unknown argument '{}'\n\n{}", flag, USAGE).into()),
+            }
+        }
+        if !public_addresses.is_empty() {
+            settings.public_addresses = public_addresses;
+        }
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("can't read settings file {}: {}", path, e))?;
+        ron::from_str(&contents).map_err(|e| format!("invalid settings file {}: {}", path, e))
+    }
+
+    /// Collects every problem at once so a broken config is fixed in one go.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = vec![];
+        if self.public_addresses.is_empty() && self.bind.ip().is_unspecified() {
+            errors.push(format!(
+                "bind address {} can't be advertised to clients, set public_addresses or --public-addr",
+                self.bind
+            ));
+        }
+        for addr in self.public_addresses.iter() {
+            if addr.ip().is_unspecified() || addr.port() == 0 {
+                errors.push(format!(
+                    "public address {} must have a concrete IP and port",
+                    addr
+                ));
+            }
+        }
+        if self.max_clients == 0 || self.max_clients > MAX_CLIENTS_LIMIT {
+            errors.push(format!(
+                "max_clients must be between 1 and {}, got {}",
+                MAX_CLIENTS_LIMIT, self.max_clients
+            ));
+        }
+        if self.tick_rate == 0 || self.tick_rate > 240 {
+            errors.push(format!(
+                "tick_rate must be between 1 and 240 Hz, got {}",
+                self.tick_rate
+            ));
+        }
+        if let Some(send_rate) = self.send_rate {
+            if send_rate == 0 || send_rate > self.tick_rate {
+                errors.push(format!(
+                    "send_rate must be between 1 and tick_rate ({}) Hz, got {}",
+                    self.tick_rate, send_rate
+                ));
+            }
+        }
+        if let Err(e) = Level::load(&self.level) {
+            errors.push(e);
+        }
+        if !(0. ..LEVEL_SIZE).contains(&self.spawn.size) {
+            errors.push(format!(
+                "spawn.size must be between 0 and the level size {}, got {}",
+                LEVEL_SIZE, self.spawn.size
+            ));
+        }
+        if !(0. ..).contains(&self.spawn.protection) {
+            errors.push(format!(
+                "spawn.protection can't be negative, got {}",
+                self.spawn.protection
+            ));
+        }
+        let projectile = &self.projectile;
+        if [projectile.speed, projectile.radius, projectile.lifetime]
+            .iter()
+            .any(|value| value.is_nan() || *value <= 0.)
+        {
+            errors.push("projectile speed, radius and lifetime must be greater than 0".to_string());
+        }
+        if !(0. ..).contains(&projectile.cooldown) || !(0. ..).contains(&projectile.impulse) {
+            errors.push("projectile cooldown and impulse can't be negative".to_string());
+        }
+        let damage = &self.damage;
+        if damage.max_health.is_nan() || damage.max_health <= 0. {
+            errors.push(format!(
+                "damage.max_health must be greater than 0, got {}",
+                damage.max_health
+            ));
+        }
+        if damage.wall_curve.is_empty()
+            || damage
+                .wall_curve
+                .windows(2)
+                .any(|pair| pair[0].0 >= pair[1].0)
+            || damage
+                .wall_curve
+                .iter()
+                .any(|(force, damage)| !(0. ..).contains(force) || !(0. ..).contains(damage))
+        {
+            errors.push(
+                "damage.wall_curve needs points with increasing forces and no negative values"
+                    .to_string(),
+            );
+        }
+        if !(0. ..).contains(&damage.projectile) || !(0. ..).contains(&damage.respawn_delay) {
+            errors.push("damage.projectile and damage.respawn_delay can't be negative".to_string());
+        }
+        let respawn = &self.respawn;
+        if !(0. ..).contains(&respawn.cooldown)
+            || !(0. ..).contains(&respawn.stuck_time)
+            || !(0. ..).contains(&respawn.stuck_speed)
+        {
+            errors
+                .push("respawn cooldown, stuck_time and stuck_speed can't be negative".to_string());
+        }
+        if self.physics.solver_iterations == 0 {
+            errors.push("physics.solver_iterations must be at least 1".to_string());
+        }
+        if !(0. ..=1.).contains(&self.physics.erp) {
+            errors.push(format!(
+                "physics.erp must be between 0 and 1, got {}",
+                self.physics.erp
+            ));
+        }
+        if self.network.available_bytes_per_tick == 0
+            || self.network.client_channel_memory_bytes == 0
+            || self.network.server_channel_memory_bytes == 0
+            || self.network.snapshot_bytes == 0
+        {
+            errors.push("network limits must be greater than 0".to_string());
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "invalid server settings:\n  {}",
+                errors.join("\n  ")
+            ))
+        }
+    }
+
+    pub fn public_addresses(&self) -> Vec<SocketAddr> {
+        if self.public_addresses.is_empty() {
+            vec![self.bind]
+        } else {
+            self.public_addresses.clone()
+        }
+    }
+
+    /// Converts seconds of simulated time to ticks, rounding up.
+    pub fn ticks(&self, secs: f32) -> u32 {
+        (secs * self.tick_rate as f32).ceil() as u32
+    }
+
+    /// Snapshots go out every this many ticks.
+    pub fn send_interval(&self) -> u32 {
+        let send_rate = self.send_rate.unwrap_or(self.tick_rate);
+        (self.tick_rate as f32 / send_rate as f32).round() as u32
+    }
+}
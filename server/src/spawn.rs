@@ -0,0 +1,107 @@
+//! Picking where a car spawns: a level spawn point no other car is on, else a free spot
+//! in the spawn area, and a short protection from damage once there.
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_garage_arena_lib::{ServerTick, SpawnPoint};
+use bevy_rapier3d::prelude::*;
+
+use crate::{reached, ServerSettings};
+
+/// Room a car needs, a bit more than its body so wheels don't touch.
+const CAR_HALF_EXTENTS: Vec3 = Vec3::new(1.5, 1., 3.);
+/// Random spots of the spawn area tried once every spawn point is taken.
+const FALLBACK_ATTEMPTS: usize = 32;
+/// How much higher the last resort drops the car, on top of whatever is in the way.
+const FALLBACK_DROP: f32 = 4.;
+
+/// The car takes no damage until `until_tick`, or until it fires.
+#[derive(Debug, Component)]
+pub struct SpawnProtection {
+    until_tick: u32,
+}
+
+#[derive(SystemParam)]
+pub struct Spawner<'w, 's> {
+    rapier: Res<'w, RapierContext>,
+    settings: Res<'w, ServerSettings>,
+    tick: Res<'w, ServerTick>,
+    points: Query<'w, 's, &'static Transform, With<SpawnPoint>>,
+}
+
+impl Spawner<'_, '_> {
+    /// Whether a car at `transform` would overlap another car. `taken` are spawns decided
+    /// this tick which physics doesn't know about yet, `ignore` the spawning car's own colliders.
+    fn is_free(&self, transform: &Transform, taken: &[Vec3], ignore: &[Entity]) -> bool {
+        let min_distance = CAR_HALF_EXTENTS.max_element() * 2.;
+        if taken
+            .iter()
+            .any(|other| other.distance(transform.translation) < min_distance)
+        {
+            return false;
+        }
+        let shape = Collider::cuboid(CAR_HALF_EXTENTS.x, CAR_HALF_EXTENTS.y, CAR_HALF_EXTENTS.z);
+        let ignored = |entity: Entity| !ignore.contains(&entity);
+        let filter = QueryFilter::new()
+            .exclude_fixed()
+            .exclude_sensors()
+            .predicate(&ignored);
+        self.rapier
+            .intersection_with_shape(transform.translation, transform.rotation, &shape, filter)
+            .is_none()
+    }
+
+    /// Where to put a car, the caller adds the translation to `taken`.
+    pub fn find(&self, taken: &[Vec3], ignore: &[Entity]) -> Transform {
+        let mut points: Vec<Transform> = self.points.iter().copied().collect();
+        // Not always the same first point, spread players over the level
+        fastrand::shuffle(&mut points);
+        if let Some(point) = points
+            .iter()
+            .find(|point| self.is_free(point, taken, ignore))
+        {
+            return *point;
+        }
+        for _ in 0..FALLBACK_ATTEMPTS {
+            let transform = self.settings.spawn.random_transform();
+            if self.is_free(&transform, taken, ignore) {
+                return transform;
+            }
+        }
+        // Crowded, better to land on another car than inside it
+        let mut transform = points
+            .first()
+            .copied()
+            .unwrap_or_else(|| self.settings.spawn.random_transform());
+        transform.translation.y += FALLBACK_DROP;
+        println!("No free spawn, dropping car at {}", transform.translation);
+        transform
+    }
+
+    pub fn protection(&self) -> SpawnProtection {
+        SpawnProtection {
+            until_tick: self
+                .tick
+                .0
+                .wrapping_add(self.settings.ticks(self.settings.spawn.protection)),
+        }
+    }
+}
+
+fn spawn_protection_system(
+    mut cmd: Commands,
+    tick: Res<ServerTick>,
+    cars: Query<(Entity, &SpawnProtection)>,
+) {
+    for (entity, protection) in cars.iter() {
+        if reached(tick.0, protection.until_tick) {
+            cmd.entity(entity).remove::<SpawnProtection>();
+        }
+    }
+}
+
+pub struct SpawnPlugin;
+
+impl Plugin for SpawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, spawn_protection_system);
+    }
+}
@@ -0,0 +1,60 @@
+use bevy_garage_arena_server::{settings::SettingsError, ServerSettings};
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn flags_override_defaults() {
+    let settings = ServerSettings::from_args(args(&[
+        "--bind",
+        "127.0.0.1:6000",
+        "--public-addr",
+        "10.0.0.1:6000",
+        "--max-clients",
+        "8",
+        "--tick-rate",
+        "30",
+        "--send-rate",
+        "15",
+    ]))
+    .unwrap();
+    assert_eq!(settings.bind, "127.0.0.1:6000".parse().unwrap());
+    assert_eq!(
+        settings.public_addresses,
+        vec!["10.0.0.1:6000".parse().unwrap()]
+    );
+    assert_eq!(settings.max_clients, 8);
+    assert_eq!(settings.tick_rate, 30);
+    assert_eq!(settings.send_interval(), 2);
+}
+
+#[test]
+fn bad_arguments_are_rejected() {
+    assert!(matches!(
+        ServerSettings::from_args(args(&["--help"])),
+        Err(SettingsError::Help)
+    ));
+    let Err(SettingsError::Invalid(e)) = ServerSettings::from_args(args(&["--nope"])) else {
+        panic!("unknown flag accepted");
+    };
+    assert!(e.contains("unknown argument '--nope'"), "{}", e);
+    assert!(ServerSettings::from_args(args(&["--tick-rate"])).is_err());
+    assert!(ServerSettings::from_args(args(&["--tick-rate", "fast"])).is_err());
+}
+
+#[test]
+fn validation_collects_every_error() {
+    let mut settings = ServerSettings {
+        max_clients: 0,
+        tick_rate: 0,
+        level: "no_such_level".to_string(),
+        ..Default::default()
+    };
+    settings.physics.erp = 2.;
+    let e = settings.validate().unwrap_err();
+    for problem in ["max_clients", "tick_rate", "no_such_level", "physics.erp"] {
+        assert!(e.contains(problem), "{}", e);
+    }
+    assert!(ServerSettings::default().validate().is_ok());
+}
@@ -1,3 +1,78 @@
+use bevy_garage_arena_client::Connection;
+use bevy_garage_arena_lib::{
+    memory::{memory_transport_pair, HOST_CLIENT_ID},
+    PlayerProfile,
+};
+use bevy_garage_arena_server::ServerSettings;
+use bevy_renet::renet::ClientId;
+
+const USAGE: &str = "Usage: bevy_garage_arena <COMMAND> [OPTIONS]
+
+Commands:
+  server [OPTIONS]        Dedicated server, see `server --help` for options
+  client [--connect ADDR] Connect to a server [default: RENET_SERVER_ADDR or 127.0.0.1:5000]
+  host [OPTIONS]          Play locally on an in-process server that others can join too,
+                          takes the same options as `server`";
+
+fn server_settings(args: Vec<String>) -> ServerSettings {
+    ServerSettings::from_args(args).unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(e.exit_code());
+    })
+}
+
 fn main() {
-    println!("Hello, world!");
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    let args: Vec<String> = args.collect();
+    match command.as_deref() {
+        Some("server") => {
+            if let Err(e) = bevy_garage_arena_server::run(server_settings(args)) {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        Some("client") => {
+            let server_addr = match args.as_slice() {
+                [] => None,
+                [flag, addr] if flag == "--connect" => Some(addr.clone()),
+                _ => {
+                    println!("{}", USAGE);
+                    std::process::exit(2);
+                }
+            };
+            bevy_garage_arena_client::run(Connection::Netcode { server_addr });
+        }
+        Some("host") => {
+            let settings = server_settings(args);
+            let profile = PlayerProfile {
+                name: "Host".to_string(),
+                ..Default::default()
+            };
+            let (server_transport, client_transport) =
+                memory_transport_pair(ClientId::from_raw(HOST_CLIENT_ID), profile.to_user_data());
+            // The window and its event loop belong to the client, on the main thread
+            let (started, start) = std::sync::mpsc::channel();
+            std::thread::spawn(move || {
+                match bevy_garage_arena_server::build_app(settings, Some(server_transport)) {
+                    Ok(mut app) => {
+                        started.send(Ok(())).unwrap();
+                        app.run();
+                    }
+                    Err(e) => started.send(Err(e)).unwrap(),
+                }
+            });
+            // Without a server the local client would wait on a dead transport
+            if let Ok(Err(e)) = start.recv() {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+            bevy_garage_arena_client::run(Connection::Memory(client_transport));
+        }
+        Some("--help" | "-h" | "help") => println!("{}", USAGE),
+        _ => {
+            println!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
 }